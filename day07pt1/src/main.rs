@@ -27,7 +27,7 @@ where
     )
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(u8)]
 enum HandRank {
     HighCard,
@@ -61,26 +61,6 @@ impl HandRank {
     }
 }
 
-impl PartialEq for HandRank {
-    fn eq(&self, other: &Self) -> bool {
-        core::mem::discriminant(self) == core::mem::discriminant(other)
-    }
-}
-
-impl Eq for HandRank {}
-
-impl PartialOrd for HandRank {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-impl Ord for HandRank {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        (*self as u8).cmp(&(*other as u8))
-    }
-}
-
 #[derive(Debug)]
 struct Hand {
     cards: [u8; 5],
@@ -142,7 +122,7 @@ impl FromStr for Hand {
 
 impl PartialEq for Hand {
     fn eq(&self, other: &Self) -> bool {
-        self.rank == other.rank
+        self.cmp(other) == std::cmp::Ordering::Equal
     }
 }
 
@@ -156,17 +136,75 @@ impl PartialOrd for Hand {
 
 impl Ord for Hand {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        match self.rank.cmp(&other.rank) {
-            std::cmp::Ordering::Equal => self
-                .cards
-                .iter()
-                .zip(other.cards.iter())
-                .find_map(|(s, o)| match s.cmp(o) {
-                    std::cmp::Ordering::Equal => None,
-                    o => Some(o),
-                })
-                .unwrap_or(std::cmp::Ordering::Equal),
-            o => o,
+        // The bet is deliberately ignored: two hands with the same cards are equal
+        self.rank
+            .cmp(&other.rank)
+            .then_with(|| self.cards.cmp(&other.cards))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::cmp::Ordering;
+
+    /// Random hands, half of them drawn from only three card values so that equal hands and
+    /// shared ranks come up often
+    fn random_hands(count: usize) -> Vec<Hand> {
+        let mut rng = StdRng::seed_from_u64(7);
+        (0..count)
+            .map(|k| {
+                let values = if k % 2 == 0 { 3 } else { 13 };
+                Hand::new(std::array::from_fn(|_| rng.gen_range(0..values)), 1)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ordering_is_reflexive() {
+        for a in &random_hands(500) {
+            assert_eq!(a.cmp(a), Ordering::Equal);
+            assert_eq!(a, a);
+        }
+    }
+
+    #[test]
+    fn ordering_is_antisymmetric() {
+        let hands = random_hands(300);
+        for a in &hands {
+            for b in &hands {
+                assert_eq!(a.cmp(b), b.cmp(a).reverse());
+                if a <= b && b <= a {
+                    assert_eq!(a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ordering_is_transitive() {
+        let hands = random_hands(60);
+        for a in &hands {
+            for b in &hands {
+                for c in &hands {
+                    if a <= b && b <= c {
+                        assert!(a <= c, "{a:?} <= {b:?} <= {c:?}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn eq_agrees_with_cmp() {
+        let hands = random_hands(300);
+        for a in &hands {
+            for b in &hands {
+                assert_eq!(a == b, a.cmp(b) == Ordering::Equal);
+                assert_eq!(a.partial_cmp(b), Some(a.cmp(b)));
+            }
         }
     }
 }
@@ -14,6 +14,34 @@ fn main() {
         .fold(0, |acc, (i, h)| acc + ((i + 1) * h.bet as usize));
 
     println!("Total winnings: {total_winnings}");
+
+    // Optionally report the odds for a partial hand, e.g. `cargo run -- KJ??? 100`
+    let mut args = std::env::args().skip(1);
+    if let Some(partial) = args.next() {
+        let partial = partial.parse::<PartialHand>().expect("valid partial hand");
+        let bid = args
+            .next()
+            .map_or(1, |b| b.parse::<u16>().expect("bid is valid u16"));
+        for (draw, name) in [
+            (Draw::WithReplacement, "with replacement"),
+            (Draw::FromDeck, "from deck"),
+        ] {
+            println!("Drawing {name}:");
+            let distribution = partial.rank_distribution(draw);
+            for rank in HandRank::ALL {
+                println!(
+                    "  {rank:?}: {}/{} ({:.4}%)",
+                    distribution.outcomes(rank),
+                    distribution.total,
+                    distribution.probability(rank) * 100.0
+                );
+            }
+            println!(
+                "  Expected winnings: {:.2}",
+                partial.expected_winnings(draw, bid, &hands)
+            );
+        }
+    }
 }
 
 fn read_lines<P>(filename: P) -> Box<dyn Iterator<Item = String>>
@@ -30,9 +58,29 @@ where
 
 const CARD_COUNT: usize = 13;
 const HAND_SIZE: usize = 5;
+const DECK_COPIES: u8 = 4; // Copies of each card in a finite deck
 type Card = u8;
 
-#[derive(Debug, Copy, Clone)]
+fn parse_card(c: char) -> Option<Card> {
+    Some(match c {
+        'A' => 12,
+        'K' => 11,
+        'Q' => 10,
+        'T' => 9,
+        '9' => 8,
+        '8' => 7,
+        '7' => 6,
+        '6' => 5,
+        '5' => 4,
+        '4' => 3,
+        '3' => 2,
+        '2' => 1,
+        'J' => 0,
+        _ => return None,
+    })
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(u8)]
 enum HandRank {
     HighCard,
@@ -45,6 +93,16 @@ enum HandRank {
 }
 
 impl HandRank {
+    const ALL: [Self; 7] = [
+        Self::HighCard,
+        Self::Pair,
+        Self::TwoPair,
+        Self::ThreeOfAKind,
+        Self::FullHouse,
+        Self::FourOfAKind,
+        Self::FiveOfAKind,
+    ];
+
     fn from(cards: [Card; HAND_SIZE]) -> Self {
         const_assert_eq!(HAND_SIZE, 5); // This method assumes a hand size of 5
         let mut quantities = [0u8; CARD_COUNT];
@@ -79,26 +137,6 @@ impl HandRank {
     }
 }
 
-impl PartialEq for HandRank {
-    fn eq(&self, other: &Self) -> bool {
-        core::mem::discriminant(self) == core::mem::discriminant(other)
-    }
-}
-
-impl Eq for HandRank {}
-
-impl PartialOrd for HandRank {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-impl Ord for HandRank {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        (*self as u8).cmp(&(*other as u8))
-    }
-}
-
 #[derive(Debug)]
 struct Hand {
     cards: [Card; HAND_SIZE],
@@ -128,22 +166,7 @@ impl From<&str> for Hand {
             .expect("bet is valid u16");
 
         for card in &mut cards {
-            *card = match hand_cards.next().expect("has card") {
-                'A' => 12,
-                'K' => 11,
-                'Q' => 10,
-                'T' => 9,
-                '9' => 8,
-                '8' => 7,
-                '7' => 6,
-                '6' => 5,
-                '5' => 4,
-                '4' => 3,
-                '3' => 2,
-                '2' => 1,
-                'J' => 0,
-                _ => panic!("invalid card"),
-            }
+            *card = parse_card(hand_cards.next().expect("has card")).expect("valid card");
         }
 
         Hand::new(cards, bet)
@@ -160,7 +183,7 @@ impl FromStr for Hand {
 
 impl PartialEq for Hand {
     fn eq(&self, other: &Self) -> bool {
-        self.rank == other.rank
+        self.cmp(other) == std::cmp::Ordering::Equal
     }
 }
 
@@ -174,17 +197,211 @@ impl PartialOrd for Hand {
 
 impl Ord for Hand {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        match self.rank.cmp(&other.rank) {
-            std::cmp::Ordering::Equal => self
-                .cards
-                .iter()
-                .zip(other.cards.iter())
-                .find_map(|(s, o)| match s.cmp(o) {
-                    std::cmp::Ordering::Equal => None,
-                    o => Some(o),
-                })
-                .unwrap_or(std::cmp::Ordering::Equal),
-            o => o,
+        // The bet is deliberately ignored: two hands with the same cards are equal
+        self.rank
+            .cmp(&other.rank)
+            .then_with(|| self.cards.cmp(&other.cards))
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Draw {
+    /// Each unknown card is any of the `CARD_COUNT` cards with equal probability
+    WithReplacement,
+    /// Unknown cards are dealt from a deck of `DECK_COPIES` of each card, minus the known cards
+    FromDeck,
+}
+
+#[derive(Debug)]
+struct PartialHand {
+    cards: [Option<Card>; HAND_SIZE],
+}
+
+impl FromStr for PartialHand {
+    type Err = String;
+
+    /// Parses a hand such as `KJ???`, where `?` marks a card yet to be drawn
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut cards = [None; HAND_SIZE];
+        let mut chars = s.chars();
+        for card in &mut cards {
+            *card = match chars.next() {
+                Some('?') => None,
+                Some(c) => Some(parse_card(c).ok_or_else(|| format!("invalid card '{c}'"))?),
+                None => return Err(format!("expected {HAND_SIZE} cards")),
+            };
+        }
+        if chars.next().is_some() {
+            return Err(format!("expected {HAND_SIZE} cards"));
+        }
+        if cards.iter().all(Option::is_some) {
+            return Err("at least one card must be unknown".to_string());
+        }
+        Ok(PartialHand { cards })
+    }
+}
+
+impl PartialHand {
+    /// Calls `f` with every way of completing the hand, along with the number of equally likely
+    /// draws that produce it
+    fn for_each_completion(&self, draw: Draw, mut f: impl FnMut([Card; HAND_SIZE], u64)) {
+        fn fill(
+            cards: &mut [Card; HAND_SIZE],
+            unknown: &[usize],
+            deck: &mut Option<[u8; CARD_COUNT]>,
+            weight: u64,
+            f: &mut impl FnMut([Card; HAND_SIZE], u64),
+        ) {
+            let Some((&i, rest)) = unknown.split_first() else {
+                f(*cards, weight);
+                return;
+            };
+            for card in 0..CARD_COUNT {
+                let copies = match deck {
+                    Some(deck) if deck[card] == 0 => continue,
+                    Some(deck) => {
+                        deck[card] -= 1;
+                        u64::from(deck[card]) + 1
+                    }
+                    None => 1,
+                };
+                cards[i] = card as Card;
+                fill(cards, rest, deck, weight * copies, f);
+                if let Some(deck) = deck {
+                    deck[card] += 1;
+                }
+            }
+        }
+
+        let mut cards = [0; HAND_SIZE];
+        let mut unknown = Vec::with_capacity(HAND_SIZE);
+        let mut deck = match draw {
+            Draw::WithReplacement => None,
+            Draw::FromDeck => Some([DECK_COPIES; CARD_COUNT]),
+        };
+        for (i, card) in self.cards.iter().enumerate() {
+            match card {
+                Some(card) => {
+                    cards[i] = *card;
+                    if let Some(deck) = &mut deck {
+                        deck[*card as usize] = deck[*card as usize]
+                            .checked_sub(1)
+                            .expect("known cards fit in the deck");
+                    }
+                }
+                None => unknown.push(i),
+            }
+        }
+        fill(&mut cards, &unknown, &mut deck, 1, &mut f);
+    }
+
+    /// The exact distribution of ranks over every way of drawing the unknown cards
+    fn rank_distribution(&self, draw: Draw) -> RankDistribution {
+        let mut distribution = RankDistribution {
+            counts: [0; HandRank::ALL.len()],
+            total: 0,
+        };
+        self.for_each_completion(draw, |cards, weight| {
+            distribution.counts[HandRank::from(cards) as usize] += weight;
+            distribution.total += weight;
+        });
+        distribution
+    }
+
+    /// The expected winnings of this hand with the given bid, when played against `others`
+    fn expected_winnings(&self, draw: Draw, bid: u16, others: &[Hand]) -> f64 {
+        let mut sorted: Vec<&Hand> = others.iter().collect();
+        sorted.sort_unstable();
+        let mut winnings = 0u128;
+        let mut total = 0u128;
+        self.for_each_completion(draw, |cards, weight| {
+            let hand = Hand::new(cards, bid);
+            let rank = sorted.partition_point(|&o| *o < hand) + 1;
+            winnings += u128::from(weight) * rank as u128 * u128::from(bid);
+            total += u128::from(weight);
+        });
+        winnings as f64 / total as f64
+    }
+}
+
+#[derive(Debug)]
+struct RankDistribution {
+    counts: [u64; HandRank::ALL.len()],
+    total: u64,
+}
+
+impl RankDistribution {
+    fn outcomes(&self, rank: HandRank) -> u64 {
+        self.counts[rank as usize]
+    }
+
+    fn probability(&self, rank: HandRank) -> f64 {
+        self.outcomes(rank) as f64 / self.total as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::cmp::Ordering;
+
+    /// Random hands, half of them drawn from only three card values so that equal hands and
+    /// shared ranks come up often
+    fn random_hands(count: usize) -> Vec<Hand> {
+        let mut rng = StdRng::seed_from_u64(7);
+        (0..count)
+            .map(|k| {
+                let values = if k % 2 == 0 { 3 } else { CARD_COUNT as Card };
+                Hand::new(std::array::from_fn(|_| rng.gen_range(0..values)), 1)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ordering_is_reflexive() {
+        for a in &random_hands(500) {
+            assert_eq!(a.cmp(a), Ordering::Equal);
+            assert_eq!(a, a);
+        }
+    }
+
+    #[test]
+    fn ordering_is_antisymmetric() {
+        let hands = random_hands(300);
+        for a in &hands {
+            for b in &hands {
+                assert_eq!(a.cmp(b), b.cmp(a).reverse());
+                if a <= b && b <= a {
+                    assert_eq!(a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ordering_is_transitive() {
+        let hands = random_hands(60);
+        for a in &hands {
+            for b in &hands {
+                for c in &hands {
+                    if a <= b && b <= c {
+                        assert!(a <= c, "{a:?} <= {b:?} <= {c:?}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn eq_agrees_with_cmp() {
+        let hands = random_hands(300);
+        for a in &hands {
+            for b in &hands {
+                assert_eq!(a == b, a.cmp(b) == Ordering::Equal);
+                assert_eq!(a.partial_cmp(b), Some(a.cmp(b)));
+            }
         }
     }
 }
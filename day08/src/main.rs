@@ -2,11 +2,20 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
-use std::str::FromStr;
 
 fn main() {
     part_1();
     part_2();
+
+    // Optionally describe the network's structure, e.g. `cargo run -- --analyse`, or write it as
+    // DOT, e.g. `cargo run -- --dot network.dot`
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        Some("--analyse") => analyse_network(),
+        Some("--dot") => write_dot(&args.next().expect("has DOT path")),
+        Some(arg) => panic!("unknown argument: {arg}"),
+        None => (),
+    }
 }
 
 fn read_lines<P>(filename: P) -> Box<dyn Iterator<Item = String>>
@@ -21,82 +30,228 @@ where
     )
 }
 
-#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
-struct Node {
-    id: [char; 3],
+type NodeId = u32;
+
+/// A network of labelled nodes, interned into dense indices
+#[derive(Debug, Default)]
+struct Network {
+    labels: Vec<String>,
+    ids: HashMap<String, NodeId>,
+    /// The (left, right) neighbours of each node, indexed by `NodeId`
+    edges: Vec<(NodeId, NodeId)>,
+    defined: Vec<bool>,
 }
 
-impl Node {
-    fn new(s: &str) -> Self {
-        s.into()
+impl Network {
+    fn intern(&mut self, label: &str) -> Result<NodeId, String> {
+        if label.is_empty() || !label.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(format!("invalid node label '{label}'"));
+        }
+        if let Some(&id) = self.ids.get(label) {
+            return Ok(id);
+        }
+        let id = NodeId::try_from(self.labels.len()).map_err(|_| "too many nodes".to_string())?;
+        self.labels.push(label.to_owned());
+        self.ids.insert(label.to_owned(), id);
+        self.edges.push((id, id));
+        self.defined.push(false);
+        Ok(id)
     }
-}
 
-impl From<&str> for Node {
-    fn from(s: &str) -> Self {
-        s.parse().unwrap()
+    fn add_line(&mut self, line: &str) -> Result<(), String> {
+        let (node, neighbours) = line
+            .split_once('=')
+            .ok_or("expected 'NODE = (LEFT, RIGHT)'")?;
+        let (left, right) = neighbours
+            .trim()
+            .strip_prefix('(')
+            .and_then(|n| n.strip_suffix(')'))
+            .and_then(|n| n.split_once(','))
+            .ok_or("expected '(LEFT, RIGHT)'")?;
+        let node = self.intern(node.trim())?;
+        let left = self.intern(left.trim())?;
+        let right = self.intern(right.trim())?;
+        if self.defined[node as usize] {
+            return Err(format!("node '{}' is defined twice", self.label(node)));
+        }
+        self.defined[node as usize] = true;
+        self.edges[node as usize] = (left, right);
+        Ok(())
+    }
+
+    fn id(&self, label: &str) -> Option<NodeId> {
+        self.ids.get(label).copied()
+    }
+
+    fn label(&self, id: NodeId) -> &str {
+        &self.labels[id as usize]
+    }
+
+    fn ids(&self) -> impl Iterator<Item = NodeId> {
+        0..self.edges.len() as NodeId
+    }
+
+    fn next(&self, id: NodeId, direction: char) -> NodeId {
+        match direction {
+            'L' => self.edges[id as usize].0,
+            'R' => self.edges[id as usize].1,
+            _ => panic!("invalid direction"),
+        }
+    }
+
+    fn neighbours(&self, id: NodeId) -> [NodeId; 2] {
+        let (left, right) = self.edges[id as usize];
+        [left, right]
+    }
+
+    fn is_start(&self, id: NodeId) -> bool {
+        self.label(id).ends_with('A')
     }
-}
 
-impl FromStr for Node {
-    type Err = ();
+    fn is_end(&self, id: NodeId) -> bool {
+        self.label(id).ends_with('Z')
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut id = ['\0'; 3];
-        let mut chars = s.chars();
-        for id_char in &mut id {
-            let c = chars.next().ok_or(())?;
-            if !c.is_ascii_uppercase() {
-                return Err(());
+    /// Renders the network in Graphviz DOT format, highlighting start and end nodes
+    fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph network {\n");
+        for id in self.ids() {
+            let label = self.label(id);
+            if self.is_start(id) {
+                dot += &format!("    \"{label}\" [style=filled, fillcolor=palegreen];\n");
+            } else if self.is_end(id) {
+                dot += &format!("    \"{label}\" [style=filled, fillcolor=salmon];\n");
+            }
+        }
+        for id in self.ids() {
+            let (left, right) = self.edges[id as usize];
+            let label = self.label(id);
+            if left == right {
+                dot += &format!(
+                    "    \"{label}\" -> \"{}\" [label=\"L/R\"];\n",
+                    self.label(left)
+                );
+            } else {
+                dot += &format!(
+                    "    \"{label}\" -> \"{}\" [label=\"L\"];\n",
+                    self.label(left)
+                );
+                dot += &format!(
+                    "    \"{label}\" -> \"{}\" [label=\"R\"];\n",
+                    self.label(right)
+                );
             }
-            *id_char = c;
         }
-        if chars.next().is_some() {
-            return Err(());
+        dot += "}\n";
+        dot
+    }
+
+    /// Marks every node reachable from `from`, ignoring the direction instructions
+    fn reachable_from(&self, from: NodeId) -> Vec<bool> {
+        let mut reached = vec![false; self.edges.len()];
+        reached[from as usize] = true;
+        let mut stack = vec![from];
+        while let Some(id) = stack.pop() {
+            for next in self.neighbours(id) {
+                if !reached[next as usize] {
+                    reached[next as usize] = true;
+                    stack.push(next);
+                }
+            }
+        }
+        reached
+    }
+
+    /// Finds the strongly connected components using Kosaraju's algorithm
+    fn strongly_connected_components(&self) -> Vec<Vec<NodeId>> {
+        // Order the nodes by when their depth-first search finishes
+        let mut visited = vec![false; self.edges.len()];
+        let mut order = Vec::with_capacity(self.edges.len());
+        for root in self.ids() {
+            if visited[root as usize] {
+                continue;
+            }
+            visited[root as usize] = true;
+            let mut stack = vec![(root, 0)];
+            while let Some((id, edge)) = stack.pop() {
+                if let Some(&next) = self.neighbours(id).get(edge) {
+                    stack.push((id, edge + 1));
+                    if !visited[next as usize] {
+                        visited[next as usize] = true;
+                        stack.push((next, 0));
+                    }
+                } else {
+                    order.push(id);
+                }
+            }
         }
 
-        Ok(Node { id })
+        // Then collect the components on the reversed graph in reverse finishing order
+        let mut reversed: Vec<Vec<NodeId>> = vec![Vec::new(); self.edges.len()];
+        for id in self.ids() {
+            for next in self.neighbours(id) {
+                if !reversed[next as usize].contains(&id) {
+                    reversed[next as usize].push(id);
+                }
+            }
+        }
+        let mut assigned = vec![false; self.edges.len()];
+        let mut components = Vec::new();
+        for &root in order.iter().rev() {
+            if assigned[root as usize] {
+                continue;
+            }
+            assigned[root as usize] = true;
+            let mut component = Vec::new();
+            let mut stack = vec![root];
+            while let Some(id) = stack.pop() {
+                component.push(id);
+                for &prev in &reversed[id as usize] {
+                    if !assigned[prev as usize] {
+                        assigned[prev as usize] = true;
+                        stack.push(prev);
+                    }
+                }
+            }
+            components.push(component);
+        }
+        components
     }
 }
 
-fn parse_input() -> (String, HashMap<Node, (Node, Node)>) {
+fn parse_input() -> Result<(String, Network), String> {
     let mut lines = read_lines("input.txt");
-    let mut nodes: HashMap<Node, (Node, Node)> = HashMap::new();
-    let directions = lines.next().expect("has first line");
-    lines
-        .skip(1)
-        .map(|l| {
-            l.split([' ', '=', '(', ',', ')'])
-                .filter(|s| !s.is_empty())
-                .map(std::borrow::ToOwned::to_owned)
-                .collect::<Vec<_>>()
-        })
-        .map_while(|v| {
-            Some((
-                v[0].parse::<Node>().ok()?,
-                v[1].parse::<Node>().ok()?,
-                v[2].parse::<Node>().ok()?,
-            ))
-        })
-        .for_each(|(a, b, c)| {
-            nodes.insert(a, (b, c));
-        });
-    (directions, nodes)
+    let mut network = Network::default();
+    let directions = lines.next().ok_or("missing directions")?;
+    if directions.is_empty() {
+        return Err("line 1: no directions".to_string());
+    }
+    if let Some(c) = directions.chars().find(|c| !matches!(c, 'L' | 'R')) {
+        return Err(format!("line 1: invalid direction '{c}'"));
+    }
+    for (i, line) in lines.enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        network
+            .add_line(&line)
+            .map_err(|e| format!("line {}: {e}", i + 2))?;
+    }
+    if let Some(id) = network.ids().find(|&id| !network.defined[id as usize]) {
+        return Err(format!("node '{}' is never defined", network.label(id)));
+    }
+    Ok((directions, network))
 }
 
 fn part_1() {
-    let (directions, nodes) = parse_input();
+    let (directions, network) = parse_input().expect("valid input");
 
-    let mut current: Node = Node::new("AAA");
-    let end = Node::new("ZZZ");
+    let mut current = network.id("AAA").expect("has AAA");
+    let end = network.id("ZZZ").expect("has ZZZ");
     let mut steps = 0;
     for direction in directions.chars().cycle() {
         steps += 1;
-        current = match direction {
-            'L' => nodes[&current].0,
-            'R' => nodes[&current].1,
-            _ => panic!("invalid direction"),
-        };
+        current = network.next(current, direction);
         if current == end {
             break;
         }
@@ -104,29 +259,193 @@ fn part_1() {
     println!("Steps to ZZZ: {steps}");
 }
 
-fn part_2() {
-    let (directions, nodes) = parse_input();
-
-    let lcm = nodes
-        .keys()
-        .filter(|k| k.id[2] == 'A')
-        .map(|k| {
-            let mut current = *k;
-            let mut steps: u64 = 0;
-            for direction in directions.chars().cycle() {
-                steps += 1;
-                current = match direction {
-                    'L' => nodes[&current].0,
-                    'R' => nodes[&current].1,
-                    _ => panic!("invalid direction"),
-                };
-                if current.id[2] == 'Z' {
-                    break;
-                }
+/// The steps at which a ghost stands on a node ending with Z: a finite prefix, followed by hits
+/// that repeat every `length` steps once the ghost's walk enters its cycle at step `start`
+#[derive(Debug)]
+struct GhostCycle {
+    prefix_hits: Vec<u64>,
+    start: u64,
+    length: u64,
+    cycle_hits: Vec<u64>,
+}
+
+impl GhostCycle {
+    fn find(network: &Network, directions: &[char], from: NodeId) -> Self {
+        // A walk's state is its node and its position in the directions
+        let mut seen: Vec<Option<u64>> = vec![None; network.edges.len() * directions.len()];
+        let mut hits = Vec::new();
+        let mut current = from;
+        let mut step: u64 = 0;
+        let start = loop {
+            let instruction = (step % directions.len() as u64) as usize;
+            let state = current as usize * directions.len() + instruction;
+            if let Some(start) = seen[state] {
+                break start;
+            }
+            seen[state] = Some(step);
+            if network.is_end(current) {
+                hits.push(step);
+            }
+            current = network.next(current, directions[instruction]);
+            step += 1;
+        };
+        let cycle_hits = hits.split_off(hits.partition_point(|&h| h < start));
+        GhostCycle {
+            prefix_hits: hits,
+            start,
+            length: step - start,
+            cycle_hits,
+        }
+    }
+
+    fn is_hit(&self, step: u64) -> bool {
+        if step < self.start {
+            self.prefix_hits.binary_search(&step).is_ok()
+        } else {
+            let step = self.start + (step - self.start) % self.length;
+            self.cycle_hits.binary_search(&step).is_ok()
+        }
+    }
+}
+
+/// Combines `x = a1 (mod m1)` and `x = a2 (mod m2)` into a single congruence, if one exists
+fn crt((a1, m1): (u128, u128), (a2, m2): (u128, u128)) -> Option<(u128, u128)> {
+    use num::Integer;
+
+    let (a1, m1, a2, m2) = (
+        i128::try_from(a1).ok()?,
+        i128::try_from(m1).ok()?,
+        i128::try_from(a2).ok()?,
+        i128::try_from(m2).ok()?,
+    );
+    let num::integer::ExtendedGcd { gcd, x, .. } = m1.extended_gcd(&m2);
+    if (a2 - a1) % gcd != 0 {
+        return None;
+    }
+    let m2_reduced = m2 / gcd;
+    let k = ((a2 - a1) / gcd).checked_mul(x)?.rem_euclid(m2_reduced);
+    let modulus = m1.checked_mul(m2_reduced)?;
+    let residue = a1.checked_add(m1.checked_mul(k)?)?.rem_euclid(modulus);
+    Some((u128::try_from(residue).ok()?, u128::try_from(modulus).ok()?))
+}
+
+/// The first step at which every ghost stands on a node ending with Z, if there is one
+fn first_common_hit(ghosts: &[GhostCycle]) -> Option<u128> {
+    // Until every ghost has entered its cycle, a common hit must be a prefix hit of the slowest
+    let slowest = ghosts.iter().max_by_key(|g| g.start)?;
+    if let Some(&step) = slowest
+        .prefix_hits
+        .iter()
+        .find(|&&step| ghosts.iter().all(|g| g.is_hit(step)))
+    {
+        return Some(u128::from(step));
+    }
+
+    // After that, each combination of cycle hits gives a congruence to solve
+    let mut congruences = vec![(0, 1)];
+    for ghost in ghosts {
+        congruences = congruences
+            .iter()
+            .flat_map(|&c| {
+                ghost.cycle_hits.iter().filter_map(move |&hit| {
+                    crt(
+                        c,
+                        (u128::from(hit % ghost.length), u128::from(ghost.length)),
+                    )
+                })
+            })
+            .collect();
+        congruences.sort_unstable();
+        congruences.dedup();
+    }
+    let earliest = u128::from(slowest.start);
+    congruences
+        .into_iter()
+        .map(|(residue, modulus)| {
+            if residue >= earliest {
+                residue
+            } else {
+                residue + (earliest - residue).div_ceil(modulus) * modulus
             }
-            steps
         })
-        .fold(1, num::integer::lcm);
+        .min()
+}
+
+fn part_2() {
+    let (directions, network) = parse_input().expect("valid input");
+    let directions: Vec<char> = directions.chars().collect();
 
-    println!("Steps until nodes end with Z: {lcm}");
+    let ghosts: Vec<GhostCycle> = network
+        .ids()
+        .filter(|&id| network.is_start(id))
+        .map(|id| GhostCycle::find(&network, &directions, id))
+        .collect();
+
+    match first_common_hit(&ghosts) {
+        Some(steps) => println!("Steps until nodes end with Z: {steps}"),
+        None => println!("Nodes never all end with Z"),
+    }
+}
+
+fn write_dot(path: &str) {
+    let (_, network) = parse_input().expect("valid input");
+    std::fs::write(path, network.to_dot()).expect("can write DOT file");
+    println!("Wrote network to {path}");
+}
+
+/// Describes the structure of the network
+fn analyse_network() {
+    let (directions, network) = parse_input().expect("valid input");
+    let directions: Vec<char> = directions.chars().collect();
+
+    let components = network.strongly_connected_components();
+    let cyclic = components
+        .iter()
+        .filter(|c| c.len() > 1 || network.neighbours(c[0]).contains(&c[0]))
+        .count();
+    println!(
+        "Strongly connected components: {} ({cyclic} cyclic)",
+        components.len()
+    );
+
+    let mut reached_by_any = vec![false; network.edges.len()];
+    let mut lcm_applies = true;
+    for start in network.ids().filter(|&id| network.is_start(id)) {
+        let reached = network.reachable_from(start);
+        let ends: Vec<&str> = network
+            .ids()
+            .filter(|&id| reached[id as usize] && network.is_end(id))
+            .map(|id| network.label(id))
+            .collect();
+        for (any, this) in reached_by_any.iter_mut().zip(&reached) {
+            *any |= *this;
+        }
+
+        // The LCM of the first hits is only the answer if each ghost hits Z exactly once per
+        // cycle, at a step that is a multiple of the cycle length
+        let ghost = GhostCycle::find(&network, &directions, start);
+        let aligned = ghost.prefix_hits.is_empty()
+            && ghost.cycle_hits.len() == 1
+            && ghost.cycle_hits[0].is_multiple_of(ghost.length);
+        lcm_applies &= aligned;
+        println!(
+            "  {} reaches [{}], cycle of {} steps from step {} with {} Z hit(s){}",
+            network.label(start),
+            ends.join(", "),
+            ghost.length,
+            ghost.start,
+            ghost.prefix_hits.len() + ghost.cycle_hits.len(),
+            if aligned { "" } else { " (not aligned)" }
+        );
+    }
+    let unreachable = reached_by_any.iter().filter(|r| !**r).count();
+    println!("Nodes unreachable from any start: {unreachable}");
+    println!(
+        "LCM shortcut {}",
+        if lcm_applies {
+            "applies"
+        } else {
+            "does not apply"
+        }
+    );
 }
@@ -1,3 +1,7 @@
+use num::rational::Ratio;
+use num::traits::{CheckedAdd, CheckedDiv, CheckedMul, CheckedSub};
+use num::{BigInt, FromPrimitive, Integer, Signed, Zero};
+use std::fmt;
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
@@ -6,6 +10,13 @@ use std::str::FromStr;
 fn main() {
     part_1();
     part_2();
+
+    // Optionally describe the polynomial behind each history, e.g. `cargo run -- --polynomials`
+    match std::env::args().nth(1).as_deref() {
+        Some("--polynomials") => part_polynomials(),
+        Some(arg) => panic!("unknown argument: {arg}"),
+        None => (),
+    }
 }
 
 fn read_lines<P>(filename: P) -> Box<dyn Iterator<Item = String>>
@@ -20,76 +31,274 @@ where
     )
 }
 
-#[derive(Debug, Clone, Eq, Hash, PartialEq)]
-struct ValueReport {
-    history: Box<[i32]>,
+/// An integer type that a history can be stored and extrapolated in
+trait Value:
+    Integer
+    + Signed
+    + Clone
+    + CheckedAdd
+    + CheckedSub
+    + CheckedMul
+    + CheckedDiv
+    + FromPrimitive
+    + FromStr
+    + fmt::Display
+{
 }
 
-impl ValueReport {
-    fn extrapolate(&self) -> i32 {
-        let mut col1 = vec![0; self.history.len()];
-        let mut col2 = vec![0; self.history.len()];
+impl<T> Value for T where
+    T: Integer
+        + Signed
+        + Clone
+        + CheckedAdd
+        + CheckedSub
+        + CheckedMul
+        + CheckedDiv
+        + FromPrimitive
+        + FromStr
+        + fmt::Display
+{
+}
 
-        col2[0] = self.history[0];
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum ReportError {
+    /// The differences never reach zero within the length of the history
+    NoZeroDifference,
+    /// Taking the differences of the given order overflowed at `position`
+    DifferenceOverflow { order: usize, position: usize },
+    /// Evaluating at `index` overflowed while adding the given term
+    EvaluationOverflow { index: i64, term: usize },
+    /// Expanding the given term into power-basis coefficients overflowed
+    CoefficientOverflow { term: usize },
+}
 
-        for (i, history_entry) in self.history.iter().skip(1).enumerate() {
-            // copy col2 to col1
-            for (c1, c2) in col1.iter_mut().zip(col2.iter()) {
-                *c1 = *c2;
+impl fmt::Display for ReportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoZeroDifference => write!(f, "differences never reach zero"),
+            Self::DifferenceOverflow { order, position } => write!(
+                f,
+                "overflow taking differences of order {order} at position {position}"
+            ),
+            Self::EvaluationOverflow { index, term } => {
+                write!(f, "overflow evaluating index {index} at term {term}")
+            }
+            Self::CoefficientOverflow { term } => {
+                write!(f, "overflow expanding coefficients at term {term}")
             }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, Hash, PartialEq)]
+struct ValueReport<T> {
+    history: Box<[T]>,
+}
+
+impl<T: Value> ValueReport<T> {
+    /// Recovers the polynomial that generates the history
+    fn polynomial(&self) -> Result<Polynomial<T>, ReportError> {
+        let mut row = self.history.to_vec();
+        let mut differences = Vec::new();
+        while row.iter().any(|v| !v.is_zero()) {
+            if row.len() == 1 {
+                return Err(ReportError::NoZeroDifference);
+            }
+            differences.push(row[0].clone());
+            let order = differences.len();
+            row = row
+                .windows(2)
+                .enumerate()
+                .map(|(position, w)| {
+                    w[1].checked_sub(&w[0])
+                        .ok_or(ReportError::DifferenceOverflow { order, position })
+                })
+                .collect::<Result<_, _>>()?;
+        }
+        Ok(Polynomial { differences })
+    }
+
+    /// Predicts the value `steps` after the end of the history, or before its start if negative
+    fn extrapolate(&self, steps: i64) -> Result<T, ReportError> {
+        let index = if steps < 0 {
+            steps
+        } else {
+            self.history.len() as i64 - 1 + steps
+        };
+        self.polynomial()?.value_at(index)
+    }
+}
 
-            // set col2[0] to current history entry
-            col2[0] = *history_entry;
+/// A polynomial over the indices of a history, in Newton forward difference form
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Polynomial<T> {
+    /// The `k`th forward difference at index 0, for each `k` up to the degree
+    differences: Vec<T>,
+}
+
+impl<T: Value> Polynomial<T> {
+    fn degree(&self) -> usize {
+        self.differences.len().saturating_sub(1)
+    }
 
-            // calculate difference between col1 and col2 and store in col2
-            for j in 1..col2.len().min(i + 2) {
-                col2[j] = col2[j - 1] - col1[j - 1];
+    /// The value at any index, where index 0 is the first entry of the history
+    fn value_at(&self, index: i64) -> Result<T, ReportError> {
+        // Sum each difference multiplied by the binomial coefficient (index choose k), which is
+        // always an integer, so each division is exact
+        let mut binomial = T::one();
+        let mut value = T::zero();
+        for (term, difference) in self.differences.iter().enumerate() {
+            let overflow = ReportError::EvaluationOverflow { index, term };
+            if term > 0 {
+                let k = T::from_usize(term).ok_or(overflow)?;
+                let factor = T::from_i64(index)
+                    .and_then(|i| i.checked_sub(&k))
+                    .and_then(|i| i.checked_add(&T::one()))
+                    .ok_or(overflow)?;
+                binomial = binomial
+                    .checked_mul(&factor)
+                    .and_then(|b| b.checked_div(&k))
+                    .ok_or(overflow)?;
             }
+            value = difference
+                .checked_mul(&binomial)
+                .and_then(|v| value.checked_add(&v))
+                .ok_or(overflow)?;
         }
+        Ok(value)
+    }
 
-        col2.iter().sum::<i32>()
+    /// The exact coefficients in the power basis, lowest power first
+    fn coefficients(&self) -> Result<Vec<Ratio<T>>, ReportError> {
+        let mut coefficients = vec![Ratio::from_integer(T::zero()); self.differences.len()];
+        // The falling factorial index * (index - 1) * ... * (index - k + 1), lowest power first
+        let mut falling = vec![T::one()];
+        let mut factorial = T::one();
+        for (term, difference) in self.differences.iter().enumerate() {
+            let overflow = ReportError::CoefficientOverflow { term };
+            if term > 0 {
+                let k = T::from_usize(term).ok_or(overflow)?;
+                factorial = factorial.checked_mul(&k).ok_or(overflow)?;
+                let shift = k.checked_sub(&T::one()).ok_or(overflow)?;
+                let mut next = vec![T::zero(); falling.len() + 1];
+                for (power, c) in falling.iter().enumerate() {
+                    next[power + 1] = next[power + 1].checked_add(c).ok_or(overflow)?;
+                    next[power] = c
+                        .checked_mul(&shift)
+                        .and_then(|c| next[power].checked_sub(&c))
+                        .ok_or(overflow)?;
+                }
+                falling = next;
+            }
+            for (coefficient, c) in coefficients.iter_mut().zip(&falling) {
+                let term = Ratio::new(
+                    difference.checked_mul(c).ok_or(overflow)?,
+                    factorial.clone(),
+                );
+                *coefficient = coefficient.checked_add(&term).ok_or(overflow)?;
+            }
+        }
+        Ok(coefficients)
     }
 }
 
-impl From<&str> for ValueReport {
-    fn from(s: &str) -> Self {
-        s.parse().unwrap()
+impl<T: Value> fmt::Display for Polynomial<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Each nonzero term as its sign and the term with the magnitude of its coefficient
+        let terms: Vec<(bool, String)> = match self.coefficients() {
+            Ok(coefficients) => coefficients
+                .iter()
+                .enumerate()
+                .rev()
+                .filter(|(_, c)| !c.is_zero())
+                .map(|(power, c)| {
+                    let magnitude = c.abs();
+                    let term = match power {
+                        0 => format!("{magnitude}"),
+                        1 => format!("({magnitude})n"),
+                        _ => format!("({magnitude})n^{power}"),
+                    };
+                    (c.is_negative(), term)
+                })
+                .collect(),
+            // Fall back to the Newton form, which never needs more than the differences
+            Err(_) => self
+                .differences
+                .iter()
+                .enumerate()
+                .filter(|(_, d)| !d.is_zero())
+                .map(|(k, d)| (d.is_negative(), format!("({})C(n, {k})", d.abs())))
+                .collect(),
+        };
+        if terms.is_empty() {
+            return write!(f, "0");
+        }
+        for (i, (negative, term)) in terms.iter().enumerate() {
+            match (i, negative) {
+                (0, true) => write!(f, "-{term}")?,
+                (0, false) => write!(f, "{term}")?,
+                (_, true) => write!(f, " - {term}")?,
+                (_, false) => write!(f, " + {term}")?,
+            }
+        }
+        Ok(())
     }
 }
 
-impl FromStr for ValueReport {
+impl<T: Value> FromStr for ValueReport<T> {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         Ok(ValueReport {
             history: s
                 .split(' ')
-                .map(|s| s.parse().unwrap())
-                .collect::<Vec<_>>()
+                .map(|s| s.parse().map_err(|_| ()))
+                .collect::<Result<Vec<_>, _>>()?
                 .into_boxed_slice(),
         })
     }
 }
 
-fn part_1() {
-    let extrapolated_value_sum = read_lines("input.txt")
-        .map(|line| {
-            let value_report: ValueReport = line.parse().expect("valid report line");
-            value_report.extrapolate()
+fn sum_extrapolated_values(steps: i64) -> i64 {
+    read_lines("input.txt")
+        .enumerate()
+        .map(|(i, line)| {
+            let value_report: ValueReport<i64> = line.parse().expect("valid report line");
+            value_report
+                .extrapolate(steps)
+                .unwrap_or_else(|e| panic!("line {}: {e}", i + 1))
         })
-        .sum::<i32>();
+        .try_fold(0i64, i64::checked_add)
+        .expect("sum does not overflow")
+}
+
+fn part_1() {
+    let extrapolated_value_sum = sum_extrapolated_values(1);
 
     println!("Sum of extrapolated values: {extrapolated_value_sum}");
 }
 
 fn part_2() {
-    let extrapolated_value_sum = read_lines("input.txt")
-        .map(|line| {
-            let mut value_report: ValueReport = line.parse().expect("valid report line");
-            value_report.history.reverse();
-            value_report.extrapolate()
-        })
-        .sum::<i32>();
+    let extrapolated_value_sum = sum_extrapolated_values(-1);
 
     println!("Sum of reverse extrapolated values: {extrapolated_value_sum}");
 }
+
+fn part_polynomials() {
+    let mut highest_degree = 0;
+    for (i, line) in read_lines("input.txt").enumerate() {
+        // Big integers, so that expanding the coefficients can never overflow
+        let value_report: ValueReport<BigInt> = line.parse().expect("valid report line");
+        match value_report.polynomial() {
+            Ok(polynomial) => {
+                if i == 0 {
+                    println!("Polynomial for line 1: {polynomial}");
+                }
+                highest_degree = highest_degree.max(polynomial.degree());
+            }
+            Err(e) => println!("Line {}: {e}", i + 1),
+        }
+    }
+
+    println!("Highest polynomial degree: {highest_degree}");
+}
@@ -5,6 +5,17 @@ use std::path::Path;
 fn main() {
     part_1();
     part_2();
+
+    // Optionally analyse the whole pipe network, e.g. `cargo run -- --network`, or render the
+    // maze, e.g. `cargo run -- --ansi` or `cargo run -- --svg maze.svg`
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        Some("--network") => part_network(),
+        Some("--ansi") => render_ansi(),
+        Some("--svg") => render_svg(&args.next().expect("has SVG path")),
+        Some(arg) => panic!("unknown argument: {arg}"),
+        None => (),
+    }
 }
 
 fn read_lines<P>(filename: P) -> Box<dyn Iterator<Item = String>>
@@ -51,11 +62,30 @@ impl Location {
         grid[self.i][self.j]
     }
 
-    fn in_grid_mut<'a>(&self, grid: &'a mut [Box<[u8]>]) -> &'a mut u8 {
-        &mut grid[self.i][self.j]
+    /// The adjacent location in the given direction, if it is within the grid
+    fn neighbour(&self, grid: &[Box<[u8]>], direction: Direction) -> Option<Location> {
+        let location = match direction {
+            Direction::North if self.i == 0 => return None,
+            Direction::West if self.j == 0 => return None,
+            _ => self.follow_direction(direction),
+        };
+        grid.get(location.i)
+            .and_then(|row| row.get(location.j))
+            .map(|_| location)
+    }
+
+    /// The direction of an adjacent location
+    fn direction_to(&self, other: Location) -> Direction {
+        match (other.i.cmp(&self.i), other.j.cmp(&self.j)) {
+            (std::cmp::Ordering::Less, _) => Direction::North,
+            (std::cmp::Ordering::Greater, _) => Direction::South,
+            (_, std::cmp::Ordering::Greater) => Direction::East,
+            (_, _) => Direction::West,
+        }
     }
 }
-#[derive(Debug, Clone, Copy)]
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Direction {
     North,
     East,
@@ -74,179 +104,467 @@ impl Direction {
     }
 }
 
+/// The directions a pipe connects in, with `S` connecting in every direction
+fn pipe_connections(pipe: u8) -> &'static [Direction] {
+    match pipe {
+        b'|' => &[Direction::North, Direction::South],
+        b'-' => &[Direction::East, Direction::West],
+        b'L' => &[Direction::North, Direction::East],
+        b'J' => &[Direction::North, Direction::West],
+        b'7' => &[Direction::South, Direction::West],
+        b'F' => &[Direction::East, Direction::South],
+        b'S' => &[
+            Direction::North,
+            Direction::East,
+            Direction::South,
+            Direction::West,
+        ],
+        _ => &[],
+    }
+}
+
+/// The pipe that connects in both of the given directions
+fn pipe_with_connections(a: Direction, b: Direction) -> u8 {
+    [b'|', b'-', b'L', b'J', b'7', b'F']
+        .into_iter()
+        .find(|&pipe| {
+            let connections = pipe_connections(pipe);
+            a != b && connections.contains(&a) && connections.contains(&b)
+        })
+        .expect("directions are distinct")
+}
+
+/// The directions from `location` to neighbouring pipes that connect back to it
 fn find_connecting_pipe_directions(
     lines: &[Box<[u8]>],
     location: Location,
     exclude: Option<Direction>,
 ) -> Vec<Direction> {
-    let mut r = vec![];
-    let location_char = &location.in_grid(lines);
-    if !matches!(exclude, Some(Direction::North))
-        && [b'S', b'|', b'L', b'J'].contains(location_char)
-        && location.i > 0
-        && [b'S', b'|', b'7', b'F'].contains(&lines[location.i - 1][location.j])
-    {
-        r.push(Direction::North);
-    }
-    if !matches!(exclude, Some(Direction::East))
-        && [b'S', b'-', b'L', b'F'].contains(location_char)
-        && location.j < lines[location.i].len() - 1
-        && [b'S', b'-', b'J', b'7'].contains(&lines[location.i][location.j + 1])
-    {
-        r.push(Direction::East);
-    }
-    if !matches!(exclude, Some(Direction::South))
-        && [b'S', b'|', b'7', b'F'].contains(location_char)
-        && location.i < lines.len() - 1
-        && [b'S', b'|', b'L', b'J'].contains(&lines[location.i + 1][location.j])
-    {
-        r.push(Direction::South);
-    }
-    if !matches!(exclude, Some(Direction::West))
-        && [b'S', b'-', b'J', b'7'].contains(location_char)
-        && location.j > 0
-        && [b'S', b'-', b'L', b'F'].contains(&lines[location.i][location.j - 1])
-    {
-        r.push(Direction::West);
-    }
-    r
-}
-
-struct BranchState {
-    location: Location,
-    prev_direction: Option<Direction>,
-    distance: u32,
+    pipe_connections(location.in_grid(lines))
+        .iter()
+        .copied()
+        .filter(|&direction| Some(direction) != exclude)
+        .filter(|&direction| {
+            location
+                .neighbour(lines, direction)
+                .is_some_and(|n| pipe_connections(n.in_grid(lines)).contains(&direction.opposite()))
+        })
+        .collect()
 }
 
-fn part_1() {
-    let lines = read_lines("input.txt")
+/// Follows the pipes from `from`, first heading in `direction`, until the path either returns to
+/// `from` or reaches a dead end. Returns the tiles visited in order, and whether the path closed.
+fn trace(grid: &[Box<[u8]>], from: Location, direction: Direction) -> (Vec<Location>, bool) {
+    let mut vertices = vec![from];
+    let mut direction = direction;
+    loop {
+        let location = vertices[vertices.len() - 1].follow_direction(direction);
+        if location == from {
+            return (vertices, true);
+        }
+        vertices.push(location);
+        match find_connecting_pipe_directions(grid, location, Some(direction.opposite())).first() {
+            Some(&next) => direction = next,
+            None => return (vertices, false),
+        }
+    }
+}
+
+/// The main loop of pipes through `S`
+#[derive(Debug)]
+struct PipeLoop {
+    /// Every tile of the loop in walking order, starting at `S`
+    vertices: Vec<Location>,
+}
+
+impl PipeLoop {
+    /// Finds a loop through `start`, trying each pipe that connects to it until one closes
+    fn find(grid: &[Box<[u8]>], start: Location) -> Option<Self> {
+        find_connecting_pipe_directions(grid, start, None)
+            .into_iter()
+            .find_map(|direction| {
+                let (vertices, closed) = trace(grid, start, direction);
+                closed.then_some(PipeLoop { vertices })
+            })
+    }
+
+    fn length(&self) -> usize {
+        self.vertices.len()
+    }
+
+    fn farthest_distance(&self) -> usize {
+        self.length() / 2
+    }
+
+    /// Twice the area enclosed by the centres of the loop's tiles, using the shoelace formula
+    fn double_area(&self) -> usize {
+        let signed: i64 = self
+            .vertices
+            .iter()
+            .zip(self.vertices.iter().cycle().skip(1))
+            .map(|(a, b)| a.j as i64 * b.i as i64 - b.j as i64 * a.i as i64)
+            .sum();
+        signed.unsigned_abs() as usize
+    }
+
+    /// The number of tiles enclosed by the loop, using Pick's theorem: `A = i + b/2 - 1`
+    fn enclosed_tiles(&self) -> usize {
+        (self.double_area() + 2 - self.length()) / 2
+    }
+
+    /// The pipe hidden under `S`, given the two loop tiles it connects to
+    fn start_pipe(&self) -> u8 {
+        let start = self.vertices[0];
+        pipe_with_connections(
+            start.direction_to(self.vertices[1]),
+            start.direction_to(*self.vertices.last().expect("loop is not empty")),
+        )
+    }
+
+    /// Whether each tile of the grid is part of the loop, inside it or outside it
+    fn classify(&self, grid: &[Box<[u8]>]) -> Vec<Vec<Tile>> {
+        let mut tiles: Vec<Vec<Tile>> = grid
+            .iter()
+            .map(|row| vec![Tile::Outside; row.len()])
+            .collect();
+        for location in &self.vertices {
+            tiles[location.i][location.j] = Tile::Loop;
+        }
+        let start = self.vertices[0];
+        for (i, row) in tiles.iter_mut().enumerate() {
+            // A tile is inside when a ray to its left crosses the loop an odd number of times,
+            // counting only the loop tiles that connect north
+            let mut inside = false;
+            for (j, tile) in row.iter_mut().enumerate() {
+                if *tile == Tile::Loop {
+                    let pipe = if (Location { i, j }) == start {
+                        self.start_pipe()
+                    } else {
+                        grid[i][j]
+                    };
+                    if pipe_connections(pipe).contains(&Direction::North) {
+                        inside = !inside;
+                    }
+                } else if inside {
+                    *tile = Tile::Inside;
+                }
+            }
+        }
+        tiles
+    }
+
+    /// Classifies each tile like `classify`, but by flooding in from the border of the grid
+    /// scaled up three times, so that the flood can squeeze between adjacent pipes
+    fn flood_fill(&self, grid: &[Box<[u8]>]) -> Vec<Vec<Tile>> {
+        let height = grid.len() * 3;
+        let width = grid.iter().map(|row| row.len()).max().unwrap_or(0) * 3;
+
+        // Each loop tile becomes a 3x3 block with walls at its centre and on each side it connects
+        let mut wall = vec![vec![false; width]; height];
+        let start = self.vertices[0];
+        for &location in &self.vertices {
+            let pipe = if location == start {
+                self.start_pipe()
+            } else {
+                location.in_grid(grid)
+            };
+            let (i, j) = (location.i * 3 + 1, location.j * 3 + 1);
+            wall[i][j] = true;
+            for direction in pipe_connections(pipe) {
+                match direction {
+                    Direction::North => wall[i - 1][j] = true,
+                    Direction::East => wall[i][j + 1] = true,
+                    Direction::South => wall[i + 1][j] = true,
+                    Direction::West => wall[i][j - 1] = true,
+                }
+            }
+        }
+
+        let mut reached = vec![vec![false; width]; height];
+        let mut stack: Vec<(usize, usize)> = (0..height)
+            .flat_map(|i| [(i, 0), (i, width - 1)])
+            .chain((0..width).flat_map(|j| [(0, j), (height - 1, j)]))
+            .filter(|&(i, j)| !wall[i][j])
+            .collect();
+        while let Some((i, j)) = stack.pop() {
+            if reached[i][j] {
+                continue;
+            }
+            reached[i][j] = true;
+            for (ni, nj) in [
+                (i.wrapping_sub(1), j),
+                (i + 1, j),
+                (i, j.wrapping_sub(1)),
+                (i, j + 1),
+            ] {
+                if ni < height && nj < width && !wall[ni][nj] && !reached[ni][nj] {
+                    stack.push((ni, nj));
+                }
+            }
+        }
+
+        grid.iter()
+            .enumerate()
+            .map(|(i, row)| {
+                (0..row.len())
+                    .map(|j| {
+                        if wall[i * 3 + 1][j * 3 + 1] {
+                            Tile::Loop
+                        } else if reached[i * 3 + 1][j * 3 + 1] {
+                            Tile::Outside
+                        } else {
+                            Tile::Inside
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Every closed loop and dead-end segment of pipes in a maze
+#[derive(Debug)]
+struct PipeNetwork {
+    /// The loop through `S`, if any choice of pipe under `S` closes one
+    main_loop: Option<PipeLoop>,
+    /// The number of pipes that `S` could connect to
+    start_candidates: usize,
+    /// Every closed loop, including the main loop
+    loops: Vec<PipeLoop>,
+    /// Every chain of pipes that ends without closing, in order from one end
+    dead_ends: Vec<Vec<Location>>,
+}
+
+impl PipeNetwork {
+    fn analyse(grid: &[Box<[u8]>], start: Location) -> Self {
+        let main_loop = PipeLoop::find(grid, start);
+        let start_candidates = find_connecting_pipe_directions(grid, start, None).len();
+
+        // With `S` replaced by its pipe, every tile connects to at most two others, so each
+        // connected group of pipes is either a loop or a chain
+        let mut resolved = grid.to_vec();
+        resolved[start.i][start.j] = main_loop.as_ref().map_or(b'.', PipeLoop::start_pipe);
+        let mut visited: Vec<Vec<bool>> = grid.iter().map(|row| vec![false; row.len()]).collect();
+        let mut loops = Vec::new();
+        let mut dead_ends = Vec::new();
+        for (i, row) in resolved.iter().enumerate() {
+            for j in 0..row.len() {
+                let location = Location { i, j };
+                if visited[i][j] || pipe_connections(location.in_grid(&resolved)).is_empty() {
+                    continue;
+                }
+
+                // Walk back to one end of the chain, or all the way round a loop
+                let mut end = location;
+                let mut direction = None;
+                while let Some(&next) = find_connecting_pipe_directions(
+                    &resolved,
+                    end,
+                    direction.map(Direction::opposite),
+                )
+                .first()
+                {
+                    end = end.follow_direction(next);
+                    direction = Some(next);
+                    if end == location {
+                        break;
+                    }
+                }
+
+                let (vertices, closed) =
+                    match find_connecting_pipe_directions(&resolved, end, None).first() {
+                        Some(&direction) => trace(&resolved, end, direction),
+                        None => (vec![end], false),
+                    };
+                for v in &vertices {
+                    visited[v.i][v.j] = true;
+                }
+                if closed {
+                    loops.push(PipeLoop { vertices });
+                } else {
+                    dead_ends.push(vertices);
+                }
+            }
+        }
+
+        PipeNetwork {
+            main_loop,
+            start_candidates,
+            loops,
+            dead_ends,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tile {
+    Loop,
+    Inside,
+    Outside,
+}
+
+fn parse_input() -> (Box<[Box<[u8]>]>, Location) {
+    let grid = read_lines("input.txt")
         .map(|s| s.into_bytes().into_boxed_slice())
         .collect::<Vec<_>>()
         .into_boxed_slice();
-    let s_location = lines
+    let s_location = grid
         .iter()
         .enumerate()
         .find_map(|(i, s)| s.iter().position(|c| *c == b'S').map(|j| Location { i, j }))
         .expect("has S");
-    let initial_connecting_pipes = find_connecting_pipe_directions(&lines, s_location, None);
+    (grid, s_location)
+}
 
-    let mut left = BranchState {
-        location: s_location.follow_direction(initial_connecting_pipes[0]),
-        prev_direction: initial_connecting_pipes.first().copied(),
-        distance: 1,
+fn part_1() {
+    let (grid, s_location) = parse_input();
+    let Some(pipe_loop) = PipeLoop::find(&grid, s_location) else {
+        println!("S is not on a loop");
+        return;
     };
+    println!("Longest distance: {}", pipe_loop.farthest_distance());
+}
+
+fn part_network() {
+    let (grid, s_location) = parse_input();
+    let network = PipeNetwork::analyse(&grid, s_location);
+    match &network.main_loop {
+        Some(pipe_loop) => {
+            println!(
+                "S is a {} pipe (of {} candidate connections)",
+                char::from(pipe_loop.start_pipe()),
+                network.start_candidates
+            );
+            let flooded = pipe_loop.flood_fill(&grid);
+            let count = |tile| flooded.iter().flatten().filter(|&&t| t == tile).count();
+            println!(
+                "Flood fill: {} inside, {} outside",
+                count(Tile::Inside),
+                count(Tile::Outside)
+            );
+        }
+        None => println!("S is not on a loop"),
+    }
+    println!("Closed loops: {}", network.loops.len());
+    println!(
+        "Dead-end segments: {} ({} tiles)",
+        network.dead_ends.len(),
+        network.dead_ends.iter().map(Vec::len).sum::<usize>()
+    );
+}
 
-    let mut right = BranchState {
-        location: s_location.follow_direction(initial_connecting_pipes[1]),
-        prev_direction: initial_connecting_pipes.get(1).copied(),
-        distance: 1,
+fn part_2() {
+    let (grid, s_location) = parse_input();
+    let Some(pipe_loop) = PipeLoop::find(&grid, s_location) else {
+        println!("S is not on a loop");
+        return;
     };
+    println!("Enclosed tiles: {}", pipe_loop.enclosed_tiles());
 
-    let mut is_left = true;
+    // Cross-check the parity scan against the flood fill, and both against Pick's theorem
+    let parity = pipe_loop.classify(&grid);
+    let flooded = pipe_loop.flood_fill(&grid);
+    assert!(parity == flooded, "parity scan and flood fill disagree");
+    let count = |tile| flooded.iter().flatten().filter(|&&t| t == tile).count();
+    assert_eq!(count(Tile::Inside), pipe_loop.enclosed_tiles());
+}
 
-    while left.location != right.location {
-        let branch = if is_left { &mut left } else { &mut right };
-        let connecting_pipes = find_connecting_pipe_directions(
-            &lines,
-            branch.location,
-            branch.prev_direction.map(Direction::opposite),
-        );
-        let new_direction = connecting_pipes.first().expect("has connecting pipe");
-        branch.location = branch.location.follow_direction(*new_direction);
-        branch.prev_direction = Some(*new_direction);
-        branch.distance += 1;
-        is_left = !is_left;
+fn box_drawing(pipe: u8) -> char {
+    match pipe {
+        b'|' => '│',
+        b'-' => '─',
+        b'L' => '└',
+        b'J' => '┘',
+        b'7' => '┐',
+        b'F' => '┌',
+        b'S' => 'S',
+        _ => '·',
     }
-    println!("Longest distance: {}", left.distance);
 }
 
-fn part_2() {
-    let mut lines = read_lines("input.txt")
-        .map(|s| s.into_bytes().into_boxed_slice())
-        .collect::<Vec<_>>()
-        .into_boxed_slice();
-    let s_location = lines
-        .iter()
-        .enumerate()
-        .find_map(|(i, s)| s.iter().position(|c| *c == b'S').map(|j| Location { i, j }))
-        .expect("has S");
-    let initial_connecting_pipes = find_connecting_pipe_directions(&lines, s_location, None);
-
-    *s_location.in_grid_mut(&mut lines) = match initial_connecting_pipes.as_slice() {
-        [Direction::North, Direction::South] | [Direction::South, Direction::North] => b'|',
-        [Direction::East, Direction::West] | [Direction::West, Direction::East] => b'-',
-        [Direction::East, Direction::South] | [Direction::South, Direction::East] => b'F',
-        [Direction::West, Direction::South] | [Direction::South, Direction::West] => b'7',
-        [Direction::North, Direction::East] | [Direction::East, Direction::North] => b'L',
-        [Direction::North, Direction::West] | [Direction::West, Direction::North] => b'J',
-        _ => panic!("unexpected direction pair"),
+/// Prints the maze with box-drawing characters, highlighting the loop and colouring the tiles
+/// inside it green and outside it blue
+fn render_ansi() {
+    let (grid, s_location) = parse_input();
+    let Some(pipe_loop) = PipeLoop::find(&grid, s_location) else {
+        println!("S is not on a loop");
+        return;
     };
+    let tiles = pipe_loop.classify(&grid);
+    for (row, tile_row) in grid.iter().zip(&tiles) {
+        let mut line = String::new();
+        for (&pipe, tile) in row.iter().zip(tile_row) {
+            let colour = match tile {
+                Tile::Loop => "\x1b[1;33m",
+                Tile::Inside => "\x1b[32m",
+                Tile::Outside => "\x1b[34m",
+            };
+            line.push_str(colour);
+            line.push(box_drawing(pipe));
+        }
+        println!("{line}\x1b[0m");
+    }
+}
 
-    let mut path = BranchState {
-        location: s_location,
-        prev_direction: None,
-        distance: 0,
+/// Writes the maze as an SVG, with the loop drawn over inside and outside tiles
+fn render_svg(path: &str) {
+    const TILE: usize = 10;
+    let (grid, s_location) = parse_input();
+    let Some(pipe_loop) = PipeLoop::find(&grid, s_location) else {
+        println!("S is not on a loop");
+        return;
     };
+    let tiles = pipe_loop.classify(&grid);
+    let height = grid.len() * TILE;
+    let width = grid.iter().map(|row| row.len()).max().unwrap_or(0) * TILE;
 
-    loop {
-        let connecting_pipes = find_connecting_pipe_directions(
-            &lines,
-            path.location,
-            path.prev_direction.map(Direction::opposite),
-        );
-        let current_char = path.location.in_grid_mut(&mut lines);
-        *current_char = match *current_char {
-            b'-' => b'~',
-            b'F' => b'f',
-            b'L' => b'l',
-            b'7' => b'>',
-            b'J' => b'j',
-            b'|' => b'+',
-            _ => panic!("unexpected path character: {current_char}"),
-        };
-        if let Some(new_direction) = connecting_pipes.first() {
-            path.location = path.location.follow_direction(*new_direction);
-            path.prev_direction = Some(*new_direction);
-            path.distance += 1;
-        } else {
-            break;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\">\n"
+    );
+    for (i, tile_row) in tiles.iter().enumerate() {
+        for (j, tile) in tile_row.iter().enumerate() {
+            let fill = match tile {
+                Tile::Loop => continue,
+                Tile::Inside => "#9d9",
+                Tile::Outside => "#bcf",
+            };
+            svg += &format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{TILE}\" height=\"{TILE}\" fill=\"{fill}\"/>\n",
+                j * TILE,
+                i * TILE
+            );
         }
     }
 
-    let enclosed_sum: usize = lines
-        .iter()
-        .map(|line| {
-            let mut sum = 0;
-            let mut enclosed = false;
-            let mut lhs_vertical_connector = None;
-            for c in line.iter() {
-                match *c {
-                    b'f' | b'l' => {
-                        enclosed = !enclosed;
-                        lhs_vertical_connector = Some(*c);
-                    }
-                    b'>' | b'j' => {
-                        if let Some(lhs) = lhs_vertical_connector {
-                            if matches!((lhs, *c), (b'f', b'>') | (b'l', b'j')) {
-                                enclosed = !enclosed;
-                            }
-                            lhs_vertical_connector = None;
-                        } else {
-                            enclosed = !enclosed;
-                        }
-                    }
-                    b'+' => enclosed = !enclosed,
-                    b'~' => (),
-                    _ => {
-                        if enclosed && matches!(*c, b'|' | b'-' | b'L' | b'J' | b'7' | b'F' | b'.')
-                        {
-                            sum += 1;
-                        }
-                    }
-                }
+    // Pipes that are not part of the loop are drawn faintly from the centre of their tile
+    for (i, row) in grid.iter().enumerate() {
+        for (j, &pipe) in row.iter().enumerate() {
+            if tiles[i][j] == Tile::Loop {
+                continue;
             }
-            sum
-        })
-        .sum();
-    println!("Enclosed tiles: {enclosed_sum}");
+            let (x, y) = (j * TILE + TILE / 2, i * TILE + TILE / 2);
+            for direction in pipe_connections(pipe) {
+                let (dx, dy) = match direction {
+                    Direction::North => (x, y - TILE / 2),
+                    Direction::East => (x + TILE / 2, y),
+                    Direction::South => (x, y + TILE / 2),
+                    Direction::West => (x - TILE / 2, y),
+                };
+                svg += &format!(
+                    "<line x1=\"{x}\" y1=\"{y}\" x2=\"{dx}\" y2=\"{dy}\" stroke=\"#888\"/>\n"
+                );
+            }
+        }
+    }
+
+    let points: Vec<String> = pipe_loop
+        .vertices
+        .iter()
+        .map(|l| format!("{},{}", l.j * TILE + TILE / 2, l.i * TILE + TILE / 2))
+        .collect();
+    svg += &format!(
+        "<polygon points=\"{}\" fill=\"none\" stroke=\"#c60\" stroke-width=\"3\"/>\n</svg>\n",
+        points.join(" ")
+    );
+    std::fs::write(path, svg).expect("can write SVG file");
 }
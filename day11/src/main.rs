@@ -1,3 +1,5 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
@@ -5,6 +7,15 @@ use std::path::Path;
 fn main() {
     part_1();
     part_2();
+
+    // Optionally try out non-uniform expansion, e.g. `cargo run -- --what-if`, or query the galaxy
+    // map, e.g. `cargo run -- --galaxy-map`
+    match std::env::args().nth(1).as_deref() {
+        Some("--what-if") => part_what_if(),
+        Some("--galaxy-map") => part_galaxy_map(),
+        Some(arg) => panic!("unknown argument: {arg}"),
+        None => (),
+    }
 }
 
 fn read_lines<P>(filename: P) -> Box<dyn Iterator<Item = String>>
@@ -19,71 +30,341 @@ where
     )
 }
 
-#[derive(Debug, Copy, Clone)]
-struct Location {
-    i: usize,
-    j: usize,
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Location<T = usize> {
+    i: T,
+    j: T,
 }
 
-fn distance_sum(empty_row_size: usize, empty_col_size: usize) -> usize {
-    let mut empty_rows = 0;
-    let mut empty_cols = Vec::<usize>::new();
-    let mut galaxies = Vec::<Location>::new();
+impl Location<u128> {
+    fn distance(&self, other: &Self) -> u128 {
+        self.i.abs_diff(other.i) + self.j.abs_diff(other.j)
+    }
+}
 
+#[derive(Debug)]
+struct Universe {
+    galaxies: Vec<Location>,
+    height: usize,
+    width: usize,
+}
+
+fn parse_universe() -> Universe {
+    let mut galaxies = Vec::<Location>::new();
+    let mut height = 0;
+    let mut width = 0;
     read_lines("input.txt").enumerate().for_each(|(i, line)| {
-        let mut row_empty = true;
+        height = i + 1;
+        width = width.max(line.len());
         for (j, byte) in line.bytes().enumerate() {
             match byte {
-                b'#' => {
-                    row_empty = false;
-                    if empty_cols.len() < j + 1 {
-                        empty_cols.resize(j + 1, empty_col_size - 1);
-                    }
-                    empty_cols[j] = 0;
-                    galaxies.push(Location {
-                        i: i + (empty_rows * (empty_row_size - 1)),
-                        j,
-                    });
-                }
+                b'#' => galaxies.push(Location { i, j }),
                 b'.' => (),
                 c => panic!("unexpected character: {c}"),
             };
         }
-        if row_empty {
-            empty_rows += 1;
-        }
     });
+    Universe {
+        galaxies,
+        height,
+        width,
+    }
+}
 
-    // Fill empty_cols with cumulative sum
-    let mut col_empty_acc = 0;
-    for col_empty in &mut empty_cols {
-        let temp = *col_empty;
-        *col_empty += col_empty_acc;
-        if temp != 0 {
-            col_empty_acc += temp;
+/// How many lines each original line along one axis becomes once the universe expands
+struct Expansion {
+    /// The width of every line that contains a galaxy
+    occupied: u64,
+    /// The width of an empty line, given its original coordinate
+    empty: Box<dyn Fn(usize) -> u64>,
+}
+
+impl Expansion {
+    fn uniform(factor: u64) -> Self {
+        Expansion {
+            occupied: 1,
+            empty: Box::new(move |_| factor),
         }
     }
 
-    let mut sum = 0;
-    for i in 0..galaxies.len() {
-        for j in i + 1..galaxies.len() {
-            let g1 = galaxies[i];
-            let g2 = galaxies[j];
-            // Manhattan distance
-            sum += g1.i.abs_diff(g2.i) + g1.j.abs_diff(g2.j);
-            // Empty rows already included in locations, just add empty columns
-            sum += empty_cols[g1.j].abs_diff(empty_cols[g2.j]);
+    fn from_fn(occupied: u64, empty: impl Fn(usize) -> u64 + 'static) -> Self {
+        Expansion {
+            occupied,
+            empty: Box::new(empty),
+        }
+    }
+
+    /// The expanded coordinate at which each original line starts, plus one past the last line
+    fn offsets(&self, occupied: &[bool]) -> Vec<u128> {
+        let mut offsets = Vec::with_capacity(occupied.len() + 1);
+        let mut offset = 0;
+        offsets.push(offset);
+        for (coord, &is_occupied) in occupied.iter().enumerate() {
+            offset += u128::from(if is_occupied {
+                self.occupied
+            } else {
+                (self.empty)(coord)
+            });
+            offsets.push(offset);
+        }
+        offsets
+    }
+}
+
+/// The universe after expansion, mapping original coordinates to expanded ones
+#[derive(Debug)]
+struct ExpandedUniverse {
+    row_offsets: Vec<u128>,
+    col_offsets: Vec<u128>,
+}
+
+impl Universe {
+    fn expand(&self, rows: &Expansion, cols: &Expansion) -> ExpandedUniverse {
+        let mut occupied_rows = vec![false; self.height];
+        let mut occupied_cols = vec![false; self.width];
+        for g in &self.galaxies {
+            occupied_rows[g.i] = true;
+            occupied_cols[g.j] = true;
+        }
+        ExpandedUniverse {
+            row_offsets: rows.offsets(&occupied_rows),
+            col_offsets: cols.offsets(&occupied_cols),
+        }
+    }
+
+    fn galaxy_map(&self, rows: &Expansion, cols: &Expansion) -> GalaxyMap {
+        let expanded = self.expand(rows, cols);
+        let galaxies: Vec<Location<u128>> = self
+            .galaxies
+            .iter()
+            .map(|&g| expanded.expanded(g))
+            .collect();
+        let mut by_row: Vec<usize> = (0..galaxies.len()).collect();
+        by_row.sort_unstable_by_key(|&g| galaxies[g].i);
+        let mut row_positions = vec![0; galaxies.len()];
+        for (position, &g) in by_row.iter().enumerate() {
+            row_positions[g] = position;
+        }
+        GalaxyMap {
+            galaxies,
+            by_row,
+            row_positions,
+        }
+    }
+}
+
+impl ExpandedUniverse {
+    /// The expanded location of the top-left of an original cell
+    fn expanded(&self, location: Location) -> Location<u128> {
+        Location {
+            i: self.row_offsets[location.i],
+            j: self.col_offsets[location.j],
+        }
+    }
+}
+
+/// The expanded galaxies, indexed in the order they appear in the input
+#[derive(Debug)]
+struct GalaxyMap {
+    galaxies: Vec<Location<u128>>,
+    /// The galaxies sorted by expanded row
+    by_row: Vec<usize>,
+    /// The position of each galaxy in `by_row`
+    row_positions: Vec<usize>,
+}
+
+impl GalaxyMap {
+    fn distance(&self, a: usize, b: usize) -> u128 {
+        self.galaxies[a].distance(&self.galaxies[b])
+    }
+
+    /// The sum of the Manhattan distances between every pair of galaxies, treating each axis
+    /// independently so that it runs in O(n log n)
+    fn distance_sum(&self) -> u128 {
+        let mut rows: Vec<u128> = self.galaxies.iter().map(|g| g.i).collect();
+        let mut cols: Vec<u128> = self.galaxies.iter().map(|g| g.j).collect();
+        rows.sort_unstable();
+        cols.sort_unstable();
+        axis_distance_sum(&rows) + axis_distance_sum(&cols)
+    }
+
+    /// The sum of the distances from each galaxy to every other galaxy
+    fn distance_totals(&self) -> Vec<u128> {
+        let mut totals = vec![0; self.galaxies.len()];
+        for axis in [|g: &Location<u128>| g.i, |g: &Location<u128>| g.j] {
+            let mut order: Vec<usize> = (0..self.galaxies.len()).collect();
+            order.sort_unstable_by_key(|&g| axis(&self.galaxies[g]));
+            let total: u128 = self.galaxies.iter().map(axis).sum();
+            let mut before = 0;
+            for (k, &g) in (0..).zip(&order) {
+                let coord = axis(&self.galaxies[g]);
+                let after = total - before - coord;
+                let count_after = order.len() as u128 - k - 1;
+                totals[g] += (coord * k - before) + (after - coord * count_after);
+                before += coord;
+            }
+        }
+        totals
+    }
+
+    /// The closest other galaxy and its distance, searching outwards from the galaxy's row and
+    /// stopping once the row distance alone exceeds the best found
+    fn nearest_neighbour(&self, galaxy: usize) -> Option<(usize, u128)> {
+        let position = *self.row_positions.get(galaxy)?;
+        let row = self.galaxies[galaxy].i;
+
+        let mut best: Option<(usize, u128)> = None;
+        for side in [
+            &mut self.by_row[..position].iter().rev() as &mut dyn Iterator<Item = &usize>,
+            &mut self.by_row[position + 1..].iter(),
+        ] {
+            for &other in side {
+                if best.is_some_and(|(_, d)| self.galaxies[other].i.abs_diff(row) > d) {
+                    break;
+                }
+                let distance = self.distance(galaxy, other);
+                if best.is_none_or(|(_, d)| distance < d) {
+                    best = Some((other, distance));
+                }
+            }
         }
+        best
+    }
+
+    /// Every pair of galaxies with their distance, as (first, second, distance)
+    fn pairs(&self) -> impl Iterator<Item = (usize, usize, u128)> + '_ {
+        (0..self.galaxies.len()).flat_map(move |a| {
+            (a + 1..self.galaxies.len()).map(move |b| (a, b, self.distance(a, b)))
+        })
+    }
+
+    /// The `k` pairs of galaxies closest together, closest first
+    fn closest_pairs(&self, k: usize) -> Vec<(usize, usize, u128)> {
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        for (a, b, distance) in self.pairs() {
+            heap.push((distance, a, b));
+            if heap.len() > k {
+                heap.pop();
+            }
+        }
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|(distance, a, b)| (a, b, distance))
+            .collect()
+    }
+
+    /// The `k` pairs of galaxies farthest apart, farthest first
+    fn farthest_pairs(&self, k: usize) -> Vec<(usize, usize, u128)> {
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        for (a, b, distance) in self.pairs() {
+            heap.push(Reverse((distance, a, b)));
+            if heap.len() > k {
+                heap.pop();
+            }
+        }
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((distance, a, b))| (a, b, distance))
+            .collect()
+    }
+
+    /// The number of pairs whose distance falls in each bucket of the given width, keyed by the
+    /// start of the bucket, or `None` if the width is zero
+    fn distance_histogram(&self, bucket_width: u128) -> Option<BTreeMap<u128, usize>> {
+        if bucket_width == 0 {
+            return None;
+        }
+        let mut histogram = BTreeMap::new();
+        for (_, _, distance) in self.pairs() {
+            *histogram
+                .entry(distance / bucket_width * bucket_width)
+                .or_insert(0) += 1;
+        }
+        Some(histogram)
+    }
+}
+
+/// The sum of the distances between every pair of sorted coordinates, using prefix sums
+fn axis_distance_sum(sorted: &[u128]) -> u128 {
+    let mut prefix = 0;
+    let mut sum = 0;
+    for (k, &coord) in (0..).zip(sorted) {
+        sum += coord * k - prefix;
+        prefix += coord;
     }
     sum
 }
 
 fn part_1() {
-    let sum = distance_sum(2, 2);
+    let sum = parse_universe()
+        .galaxy_map(&Expansion::uniform(2), &Expansion::uniform(2))
+        .distance_sum();
     println!("Sum of shortest distances (x2 expansion): {sum}");
 }
 
 fn part_2() {
-    let sum = distance_sum(1_000_000, 1_000_000);
+    let expansion = Expansion::uniform(1_000_000);
+    let sum = parse_universe()
+        .galaxy_map(&expansion, &expansion)
+        .distance_sum();
     println!("Sum of shortest distances (x1M expansion): {sum}");
 }
+
+fn part_what_if() {
+    let universe = parse_universe();
+
+    // Each empty line expands by one more than its original coordinate, and occupied lines double
+    let rows = Expansion::from_fn(2, |coord| coord as u64 + 1);
+    let cols = Expansion::from_fn(2, |coord| coord as u64 + 1);
+    let sum = universe.galaxy_map(&rows, &cols).distance_sum();
+    println!(
+        "Sum of shortest distances (empty line n expands n+1 times, occupied lines x2): {sum}"
+    );
+
+    let last = *universe.galaxies.last().expect("has galaxy");
+    let expanded = universe.expand(&rows, &cols).expanded(last);
+    println!(
+        "Last galaxy ({}, {}) expands to ({}, {})",
+        last.i, last.j, expanded.i, expanded.j
+    );
+}
+
+fn part_galaxy_map() {
+    let map = parse_universe().galaxy_map(&Expansion::uniform(2), &Expansion::uniform(2));
+
+    // Galaxies are numbered from 1, as in the puzzle
+    if let Some((other, distance)) = map.nearest_neighbour(0) {
+        println!(
+            "Nearest neighbour of galaxy 1: galaxy {} at distance {distance}",
+            other + 1
+        );
+    }
+    for (name, pairs) in [
+        ("Closest", map.closest_pairs(3)),
+        ("Farthest", map.farthest_pairs(3)),
+    ] {
+        let pairs: Vec<String> = pairs
+            .iter()
+            .map(|(a, b, distance)| format!("{}-{} ({distance})", a + 1, b + 1))
+            .collect();
+        println!("{name} pairs: {}", pairs.join(", "));
+    }
+
+    let totals = map.distance_totals();
+    if let Some((galaxy, total)) = totals.iter().enumerate().max_by_key(|(_, &t)| t) {
+        println!(
+            "Most remote galaxy: {} (total distance {total})",
+            galaxy + 1
+        );
+    }
+    assert_eq!(totals.iter().sum::<u128>(), map.distance_sum() * 2);
+
+    let bucket_width = 50;
+    println!("Distance histogram:");
+    for (start, count) in map
+        .distance_histogram(bucket_width)
+        .expect("bucket width is not zero")
+    {
+        println!("  {start:>4}-{:<4} {count}", start + bucket_width - 1);
+    }
+}
@@ -1,14 +1,34 @@
-use lru::LruCache;
+use num::{BigUint, One};
+use rand::Rng;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::iter::once;
-use std::num::NonZeroUsize;
 use std::path::Path;
 use std::str::FromStr;
 
 fn main() {
     part_1();
     part_2();
+
+    // Optionally list the arrangements of a record, e.g. `cargo run -- "???.### 1,1,3"`, solve a
+    // nonogram, e.g. `cargo run -- --nonogram clues.txt`, or count the arrangements of a heavily
+    // unfolded record, e.g. `cargo run -- --unfold-many`
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        Some("--unfold-many") => part_unfold_many(),
+        Some("--nonogram") => solve_nonogram(&args.next().expect("has clues path")),
+        Some(record) => {
+            let record = record.parse::<SpringRecord>().expect("valid record");
+            for arrangement in record.arrangements_iter() {
+                println!("{}", render(&arrangement));
+            }
+            if let Some(sample) = record.sample(&mut rand::thread_rng()) {
+                println!("Random arrangement: {}", render(&sample));
+            }
+        }
+        None => (),
+    }
 }
 
 fn read_lines<P>(filename: P) -> Box<dyn Iterator<Item = String>>
@@ -41,6 +61,21 @@ impl From<u8> for Condition {
     }
 }
 
+impl From<Condition> for char {
+    fn from(value: Condition) -> Self {
+        match value {
+            Condition::Operational => '.',
+            Condition::Damaged => '#',
+            Condition::Unknown => '?',
+        }
+    }
+}
+
+fn render(conditions: &[Condition]) -> String {
+    conditions.iter().copied().map(char::from).collect()
+}
+
+#[derive(Clone)]
 struct SpringRecord {
     conditions: Box<[Condition]>,
     contiguous: Box<[u8]>,
@@ -77,115 +112,458 @@ impl From<&str> for SpringRecord {
 }
 
 impl SpringRecord {
-    fn unfold(&mut self) {
-        let mut unfolded_conditions = (0..5)
-            .flat_map(|_| self.conditions.iter().chain(once(&Condition::Unknown)))
+    /// Repeats the record `copies` times, joining the conditions with `separator`
+    fn unfold(&mut self, copies: usize, separator: Condition) {
+        let mut unfolded_conditions = (0..copies)
+            .flat_map(|_| self.conditions.iter().chain(once(&separator)))
             .copied()
             .collect::<Vec<_>>();
-        unfolded_conditions.pop().unwrap();
+        unfolded_conditions.pop();
         self.conditions = unfolded_conditions.into_boxed_slice();
 
-        self.contiguous = (0..5)
+        self.contiguous = (0..copies)
             .flat_map(|_| self.contiguous.as_ref())
             .copied()
             .collect::<Vec<_>>()
             .into_boxed_slice();
     }
 
-    fn arrangements(&self) -> usize {
-        let mut cache: LruCache<(usize, usize), usize> =
-            LruCache::new(NonZeroUsize::new(100).unwrap());
-
-        Self::arrangements_rec(&self.conditions, &self.contiguous, 0, &mut cache)
-    }
-
-    fn arrangements_rec(
-        conditions: &[Condition],
-        contiguous_blocks: &[u8],
-        offset: usize,
-        cache: &mut LruCache<(usize, usize), usize>,
-    ) -> usize {
-        if contiguous_blocks.is_empty() {
-            // No more items, base case
-            return 1;
-        }
-        if let Some(&cached_result) = cache.get(&(contiguous_blocks.len(), offset)) {
-            return cached_result;
-        }
-        let is_last_contiguous_block = contiguous_blocks.len() > 1;
-        let contiguous_block = contiguous_blocks[0];
-        let required_len = if is_last_contiguous_block {
-            contiguous_block + 1
-        } else {
-            contiguous_block
-        } as usize;
-
-        let remaining_space_required = contiguous_blocks
+    /// For each block index modulo the number of blocks, and each length of block already in
+    /// progress, the ways of reading `cells` as (blocks completed, block length in progress,
+    /// number of ways)
+    fn transfer(&self, cells: &[Condition]) -> Vec<Vec<Vec<(usize, usize, u128)>>> {
+        let k = self.contiguous.len();
+        let block = |index: usize| self.contiguous[index % k] as usize;
+        (0..k)
+            .map(|residue| {
+                (0..=block(residue))
+                    .map(|run| {
+                        let mut states: HashMap<(usize, usize), u128> =
+                            HashMap::from([((0, run), 1)]);
+                        for &cell in cells {
+                            let mut next = HashMap::new();
+                            for ((completed, run), ways) in states {
+                                let length = block(residue + completed);
+                                if !matches!(cell, Condition::Damaged) {
+                                    if run == 0 {
+                                        *next.entry((completed, 0)).or_default() += ways;
+                                    } else if run == length {
+                                        *next.entry((completed + 1, 0)).or_default() += ways;
+                                    }
+                                }
+                                if !matches!(cell, Condition::Operational) && run < length {
+                                    *next.entry((completed, run + 1)).or_default() += ways;
+                                }
+                            }
+                            states = next;
+                        }
+                        states
+                            .into_iter()
+                            .map(|((completed, run), ways)| (completed, run, ways))
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The number of arrangements of the record unfolded `copies` times with `separator`, without
+    /// building the unfolded record. Each copy is one transfer step over the state at the
+    /// boundary between copies: the blocks completed so far and the length of any block in
+    /// progress.
+    fn unfolded_arrangements(&self, copies: usize, separator: Condition) -> BigUint {
+        let k = self.contiguous.len();
+        if copies == 0 {
+            return BigUint::one();
+        }
+        if k == 0 {
+            let damaged = |c: &Condition| matches!(c, Condition::Damaged);
+            let possible =
+                !self.conditions.iter().any(damaged) && (copies == 1 || !damaged(&separator));
+            return BigUint::from(u8::from(possible));
+        }
+
+        let total = k * copies;
+        let first = self.transfer(&self.conditions);
+        let repeated = self.transfer(
+            &once(separator)
+                .chain(self.conditions.iter().copied())
+                .collect::<Vec<_>>(),
+        );
+        let max_completed = repeated
             .iter()
-            .skip(1)
-            .fold(0, |acc, x| acc + x + 1)
-            .saturating_sub(1) as usize;
-
-        (0..=conditions
-            .len()
-            .saturating_sub(offset)
-            .saturating_sub(required_len)
-            .saturating_sub(remaining_space_required))
-            .map(|position| {
-                let skipped = &conditions[offset..offset + position];
-                if skipped.iter().any(|c| matches!(c, Condition::Damaged)) {
-                    // Damaged spring was skipped
-                    return 0;
+            .flatten()
+            .flatten()
+            .map(|&(completed, _, _)| completed)
+            .max()
+            .unwrap_or(0);
+
+        // Drop states that overshoot, or that could not complete every block in the copies left
+        let viable = |completed: usize, run: usize, copies_left: usize| {
+            completed + usize::from(run > 0) <= total
+                && completed + copies_left * max_completed + 1 >= total
+        };
+
+        let mut states: HashMap<(usize, usize), BigUint> = HashMap::new();
+        for &(completed, run, ways) in &first[0][0] {
+            if viable(completed, run, copies - 1) {
+                *states.entry((completed, run)).or_default() += ways;
+            }
+        }
+        for copy in 1..copies {
+            let mut next: HashMap<(usize, usize), BigUint> = HashMap::new();
+            for ((completed, run), ways) in &states {
+                for &(step, next_run, step_ways) in &repeated[completed % k][*run] {
+                    if viable(completed + step, next_run, copies - copy - 1) {
+                        *next.entry((completed + step, next_run)).or_default() += ways * step_ways;
+                    }
+                }
+            }
+            states = next;
+        }
+
+        // Every block is complete, possibly with the last one running up to the end
+        let last_block = self.contiguous[k - 1] as usize;
+        [(total, 0), (total - 1, last_block)]
+            .iter()
+            .filter_map(|state| states.get(state))
+            .sum()
+    }
+
+    /// The number of springs from each position that could all be damaged
+    fn damageable_runs(&self) -> Vec<usize> {
+        let n = self.conditions.len();
+        let mut damageable_run = vec![0; n + 1];
+        for position in (0..n).rev() {
+            if !matches!(self.conditions[position], Condition::Operational) {
+                damageable_run[position] = damageable_run[position + 1] + 1;
+            }
+        }
+        damageable_run
+    }
+
+    /// The position after the given block and its operational spacer when the block starts at
+    /// `position`, or `None` if it doesn't fit there
+    fn place_block(
+        &self,
+        damageable_run: &[usize],
+        position: usize,
+        block: usize,
+    ) -> Option<usize> {
+        let n = self.conditions.len();
+        let end = position + self.contiguous[block] as usize;
+        (damageable_run[position] >= self.contiguous[block] as usize
+            && (end == n || !matches!(self.conditions[end], Condition::Damaged)))
+        .then_some((end + 1).min(n))
+    }
+
+    /// The number of ways to arrange the blocks from each block index onwards in the conditions
+    /// from each position onwards, as `table[position][block]`
+    fn arrangement_table(&self) -> Vec<Vec<usize>> {
+        let n = self.conditions.len();
+        let k = self.contiguous.len();
+        let damageable_run = self.damageable_runs();
+
+        let mut table = vec![vec![0; k + 1]; n + 1];
+        table[n][k] = 1;
+        for position in (0..n).rev() {
+            for block in 0..=k {
+                let mut ways = 0;
+                if !matches!(self.conditions[position], Condition::Damaged) {
+                    // Leave this spring operational
+                    ways += table[position + 1][block];
                 }
+                if block < k {
+                    // Start the next block here, followed by an operational spacer if not at the end
+                    if let Some(next) = self.place_block(&damageable_run, position, block) {
+                        ways += table[next][block + 1];
+                    }
+                }
+                table[position][block] = ways;
+            }
+        }
+        table
+    }
+
+    fn arrangements(&self) -> usize {
+        self.arrangement_table()[0][0]
+    }
+
+    /// The `index`th arrangement, ordering arrangements lexicographically with operational springs
+    /// before damaged ones, found by walking the arrangement counts
+    fn nth_arrangement(&self, table: &[Vec<usize>], mut index: usize) -> Option<Vec<Condition>> {
+        if index >= table[0][0] {
+            return None;
+        }
+        let n = self.conditions.len();
+        let mut arrangement = Vec::with_capacity(n);
+        let mut block = 0;
+        while arrangement.len() < n {
+            let position = arrangement.len();
+            if !matches!(self.conditions[position], Condition::Damaged) {
+                let operational = table[position + 1][block];
+                if index < operational {
+                    arrangement.push(Condition::Operational);
+                    continue;
+                }
+                index -= operational;
+            }
+            // The remaining arrangements all start the next block here
+            let end = position + self.contiguous[block] as usize;
+            arrangement.resize(end, Condition::Damaged);
+            if end < n {
+                arrangement.push(Condition::Operational);
+            }
+            block += 1;
+        }
+        Some(arrangement)
+    }
+
+    /// Every arrangement, in the order of `nth_arrangement`
+    fn arrangements_iter(&self) -> impl Iterator<Item = Vec<Condition>> + '_ {
+        let table = self.arrangement_table();
+        (0..table[0][0]).map_while(move |index| self.nth_arrangement(&table, index))
+    }
+
+    /// An arrangement chosen uniformly at random, if there are any
+    fn sample(&self, rng: &mut impl Rng) -> Option<Vec<Condition>> {
+        let table = self.arrangement_table();
+        if table[0][0] == 0 {
+            return None;
+        }
+        self.nth_arrangement(&table, rng.gen_range(0..table[0][0]))
+    }
 
-                let placement =
-                    &conditions[offset + position..offset + position + contiguous_block as usize];
-                if placement
-                    .iter()
-                    .any(|c| matches!(c, Condition::Operational))
+    /// The conditions shared by every arrangement, with the rest left unknown, or `None` if there
+    /// are no arrangements. Only whether arrangements exist matters, so this never counts them:
+    /// a backward pass finds the states that can still be completed, then a forward pass walks
+    /// the completable states reachable from the start and records what each spring can be.
+    fn forced_conditions(&self) -> Option<Box<[Condition]>> {
+        let n = self.conditions.len();
+        let k = self.contiguous.len();
+        let damageable_run = self.damageable_runs();
+
+        // Whether the springs from each position can hold the blocks from each block onwards
+        let mut completable = vec![vec![false; k + 1]; n + 1];
+        completable[n][k] = true;
+        for position in (0..n).rev() {
+            for block in 0..=k {
+                completable[position][block] =
+                    (!matches!(self.conditions[position], Condition::Damaged)
+                        && completable[position + 1][block])
+                        || (block < k
+                            && self
+                                .place_block(&damageable_run, position, block)
+                                .is_some_and(|next| completable[next][block + 1]));
+            }
+        }
+        if !completable[0][0] {
+            return None;
+        }
+
+        // Damaged blocks are recorded as +1 at their start and -1 after their end, so that a
+        // running sum shows whether any block can cover each spring
+        let mut reachable = vec![vec![false; k + 1]; n + 1];
+        reachable[0][0] = true;
+        let mut can_be_operational = vec![false; n];
+        let mut damaged_edges = vec![0isize; n + 1];
+        for position in 0..n {
+            for block in 0..=k {
+                if !reachable[position][block] {
+                    continue;
+                }
+                if !matches!(self.conditions[position], Condition::Damaged)
+                    && completable[position + 1][block]
                 {
-                    // Position contains operational springs
-                    return 0;
+                    reachable[position + 1][block] = true;
+                    can_be_operational[position] = true;
+                }
+                if block == k {
+                    continue;
+                }
+                if let Some(next) = self.place_block(&damageable_run, position, block) {
+                    if completable[next][block + 1] {
+                        reachable[next][block + 1] = true;
+                        let end = position + self.contiguous[block] as usize;
+                        damaged_edges[position] += 1;
+                        damaged_edges[end] -= 1;
+                        if end < n {
+                            can_be_operational[end] = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut damaged_cover = 0;
+        Some(
+            (0..n)
+                .map(|position| {
+                    damaged_cover += damaged_edges[position];
+                    match (can_be_operational[position], damaged_cover > 0) {
+                        (true, false) => Condition::Operational,
+                        (false, true) => Condition::Damaged,
+                        _ => Condition::Unknown,
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+
+/// A nonogram puzzle, where each row and column is a spring record with its conditions unknown
+struct Nonogram {
+    rows: Vec<Box<[u8]>>,
+    cols: Vec<Box<[u8]>>,
+}
+
+type Picture = Vec<Vec<Condition>>;
+
+#[derive(Debug)]
+enum Solutions {
+    None,
+    Unique(Picture),
+    /// Two of the solutions, out of possibly more
+    Multiple(Picture, Picture),
+}
+
+impl FromStr for Nonogram {
+    type Err = ();
+
+    /// Parses one line of comma-separated clues per row, a blank line, then one per column, with
+    /// `0` marking a line with no damaged springs
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (rows, cols) = s.split_once("\n\n").ok_or(())?;
+        let parse_clues = |block: &str| {
+            block
+                .lines()
+                .map(|line| {
+                    line.split(',')
+                        .map(|n| n.trim().parse::<u8>().map_err(|_| ()))
+                        .filter(|n| *n != Ok(0))
+                        .collect::<Result<Box<[u8]>, ()>>()
+                })
+                .collect::<Result<Vec<_>, ()>>()
+        };
+        Ok(Nonogram {
+            rows: parse_clues(rows)?,
+            cols: parse_clues(cols.trim_end())?,
+        })
+    }
+}
+
+impl Nonogram {
+    fn solve(&self) -> Solutions {
+        let picture = vec![vec![Condition::Unknown; self.cols.len()]; self.rows.len()];
+        let mut solutions = Vec::new();
+        self.search(picture, &mut solutions);
+        let mut solutions = solutions.into_iter();
+        match (solutions.next(), solutions.next()) {
+            (None, _) => Solutions::None,
+            (Some(solution), None) => Solutions::Unique(solution),
+            (Some(a), Some(b)) => Solutions::Multiple(a, b),
+        }
+    }
+
+    /// Line-solves the picture, then backtracks on the first unknown cell, stopping once two
+    /// solutions are found
+    fn search(&self, mut picture: Picture, solutions: &mut Vec<Picture>) {
+        if solutions.len() >= 2 || !self.line_solve(&mut picture) {
+            return;
+        }
+        let unknown = picture.iter().enumerate().find_map(|(i, row)| {
+            row.iter()
+                .position(|c| matches!(c, Condition::Unknown))
+                .map(|j| (i, j))
+        });
+        match unknown {
+            None => solutions.push(picture),
+            Some((i, j)) => {
+                for condition in [Condition::Damaged, Condition::Operational] {
+                    let mut guess = picture.clone();
+                    guess[i][j] = condition;
+                    self.search(guess, solutions);
                 }
+            }
+        }
+    }
 
-                if is_last_contiguous_block {
-                    if matches!(
-                        conditions[offset + position + required_len - 1],
-                        Condition::Damaged
-                    ) {
-                        // No room for operational spacer after placement
-                        return 0;
+    /// Repeatedly fills in the cells forced by each row and column until nothing changes,
+    /// returning false if some line has no arrangements
+    fn line_solve(&self, picture: &mut Picture) -> bool {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (i, clue) in self.rows.iter().enumerate() {
+                let record = SpringRecord {
+                    conditions: picture[i].clone().into_boxed_slice(),
+                    contiguous: clue.clone(),
+                };
+                let Some(forced) = record.forced_conditions() else {
+                    return false;
+                };
+                for (cell, condition) in picture[i].iter_mut().zip(forced.iter()) {
+                    if matches!(cell, Condition::Unknown)
+                        && !matches!(condition, Condition::Unknown)
+                    {
+                        *cell = *condition;
+                        changed = true;
                     }
-                } else {
-                    let remaining = &conditions[offset + position + contiguous_block as usize..];
-                    if remaining.iter().any(|c| matches!(c, Condition::Damaged)) {
-                        // Damaged spring left over
-                        return 0;
+                }
+            }
+            for (j, clue) in self.cols.iter().enumerate() {
+                let record = SpringRecord {
+                    conditions: picture.iter().map(|row| row[j]).collect(),
+                    contiguous: clue.clone(),
+                };
+                let Some(forced) = record.forced_conditions() else {
+                    return false;
+                };
+                for (row, condition) in picture.iter_mut().zip(forced.iter()) {
+                    if matches!(row[j], Condition::Unknown)
+                        && !matches!(condition, Condition::Unknown)
+                    {
+                        row[j] = *condition;
+                        changed = true;
                     }
                 }
+            }
+        }
+        true
+    }
+}
 
-                let child_arrangements = Self::arrangements_rec(
-                    conditions,
-                    &contiguous_blocks[1..],
-                    offset + position + required_len,
-                    cache,
-                );
-                cache.put(
-                    (
-                        contiguous_blocks.len() - 1,
-                        offset + position + required_len,
-                    ),
-                    child_arrangements,
-                );
-                child_arrangements
-            })
-            .sum()
+fn solve_nonogram(path: &str) {
+    let nonogram: Nonogram = std::fs::read_to_string(path)
+        .expect("can read clues")
+        .parse()
+        .expect("valid clues");
+    let print = |picture: &Picture| {
+        for row in picture {
+            println!("{}", render(row));
+        }
+    };
+    match nonogram.solve() {
+        Solutions::None => println!("No solution"),
+        Solutions::Unique(picture) => {
+            println!("Unique solution:");
+            print(&picture);
+        }
+        Solutions::Multiple(a, b) => {
+            println!("Multiple solutions, including:");
+            print(&a);
+            println!();
+            print(&b);
+        }
     }
 }
 
 fn part_1() {
     let sum: usize = read_lines("input.txt")
-        .map(|l| l.parse::<SpringRecord>().expect("valid row").arrangements())
+        .map(|l| {
+            let record = l.parse::<SpringRecord>().expect("valid row");
+            record.arrangements()
+        })
         .sum();
 
     println!("Sum of operational arrangements: {sum}");
@@ -195,10 +573,119 @@ fn part_2() {
     let sum: usize = read_lines("input.txt")
         .map(|l| {
             let mut record = l.parse::<SpringRecord>().expect("valid row");
-            record.unfold();
+            record.unfold(5, Condition::Unknown);
             record.arrangements()
         })
         .sum();
 
     println!("Sum of operational arrangements (unfolded): {sum}");
 }
+
+fn part_unfold_many() {
+    let line = read_lines("input.txt").next().expect("has first row");
+    let record = line.parse::<SpringRecord>().expect("valid row");
+    let arrangements = record.unfolded_arrangements(1000, Condition::Unknown);
+
+    println!("Operational arrangements of the first row (unfolded x1000): {arrangements}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The puzzle's example records, with their arrangement counts before and after unfolding
+    const EXAMPLES: [(&str, usize, usize); 6] = [
+        ("???.### 1,1,3", 1, 1),
+        (".??..??...?##. 1,1,3", 4, 16384),
+        ("?#?#?#?#?#?#?#? 1,3,1,6", 1, 1),
+        ("????.#...#... 4,1,1", 1, 16),
+        ("????.######..#####. 1,6,5", 4, 2500),
+        ("?###???????? 3,2,1", 10, 506250),
+    ];
+
+    const RECORDS: [&str; 4] = [
+        "?#?.??#??? 1,2",
+        "??????? 2,1",
+        "#??#??.? 1,1,1",
+        "?.?#?? 3",
+    ];
+
+    /// Counts the arrangements by trying every assignment of the unknown springs
+    fn arrangements_brute_force(record: &SpringRecord) -> usize {
+        fn count(conditions: &mut [Condition], position: usize, contiguous: &[u8]) -> usize {
+            let Some(offset) = conditions[position..]
+                .iter()
+                .position(|c| matches!(c, Condition::Unknown))
+            else {
+                let blocks: Vec<u8> = conditions
+                    .split(|c| matches!(c, Condition::Operational))
+                    .filter(|block| !block.is_empty())
+                    .map(|block| block.len() as u8)
+                    .collect();
+                return usize::from(blocks == contiguous);
+            };
+            let unknown = position + offset;
+            let mut sum = 0;
+            for condition in [Condition::Operational, Condition::Damaged] {
+                conditions[unknown] = condition;
+                sum += count(conditions, unknown + 1, contiguous);
+            }
+            conditions[unknown] = Condition::Unknown;
+            sum
+        }
+
+        count(&mut record.conditions.to_vec(), 0, &record.contiguous)
+    }
+
+    fn records() -> impl Iterator<Item = SpringRecord> {
+        EXAMPLES
+            .iter()
+            .map(|(line, _, _)| *line)
+            .chain(RECORDS)
+            .map(|line| line.parse().expect("valid row"))
+    }
+
+    #[test]
+    fn examples_match_the_puzzle() {
+        for (line, folded, unfolded) in EXAMPLES {
+            let mut record: SpringRecord = line.parse().expect("valid row");
+            assert_eq!(record.arrangements(), folded, "{line}");
+            record.unfold(5, Condition::Unknown);
+            assert_eq!(record.arrangements(), unfolded, "{line}");
+        }
+    }
+
+    #[test]
+    fn arrangements_match_brute_force() {
+        for record in records() {
+            assert_eq!(
+                record.arrangements(),
+                arrangements_brute_force(&record),
+                "{}",
+                render(&record.conditions)
+            );
+        }
+    }
+
+    #[test]
+    fn unfolded_arrangements_match_unfolding() {
+        for record in records() {
+            for copies in 1..=5 {
+                for separator in [
+                    Condition::Operational,
+                    Condition::Damaged,
+                    Condition::Unknown,
+                ] {
+                    let mut unfolded = record.clone();
+                    unfolded.unfold(copies, separator);
+                    assert_eq!(
+                        record.unfolded_arrangements(copies, separator),
+                        BigUint::from(unfolded.arrangements()),
+                        "{} x{copies} {separator:?}",
+                        render(&record.conditions)
+                    );
+                }
+            }
+        }
+    }
+}
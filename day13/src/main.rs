@@ -1,5 +1,5 @@
-#![feature(let_chains)]
 use std::cmp::min;
+use std::fmt;
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
@@ -7,6 +7,15 @@ use std::path::Path;
 fn main() {
     part_1();
     part_2();
+
+    // Optionally count the mirror lines by number of smudges, e.g. `cargo run -- --smudges`, or
+    // look for other symmetries, e.g. `cargo run -- --symmetries`
+    match std::env::args().nth(1).as_deref() {
+        Some("--smudges") => part_smudges(),
+        Some("--symmetries") => part_symmetries(),
+        Some(arg) => panic!("unknown argument: {arg}"),
+        None => (),
+    }
 }
 
 fn read_lines<P>(filename: P) -> Box<dyn Iterator<Item = String>>
@@ -23,101 +32,315 @@ where
 
 struct Grid {
     cells: Box<[Box<[u8]>]>,
+    /// Each row as a bitmask in 128-bit words, with bit `j % 128` of word `j / 128` set if column
+    /// `j` is `#`
+    row_masks: Box<[Box<[u128]>]>,
+    /// Each column as a bitmask in 128-bit words, with bit `i % 128` of word `i / 128` set if row
+    /// `i` is `#`
+    col_masks: Box<[Box<[u128]>]>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Axis {
+    /// A mirror line between two rows
+    Horizontal,
+    /// A mirror line between two columns
+    Vertical,
+}
+
+#[derive(Debug)]
+struct Reflection {
+    axis: Axis,
+    /// The row or column just before the mirror line
+    line: usize,
+    /// The (row, column) of each cell that differs from its reflection, on the near side
+    smudges: Vec<(usize, usize)>,
+}
+
+impl Reflection {
+    fn summary(&self) -> usize {
+        match self.axis {
+            Axis::Horizontal => (self.line + 1) * 100,
+            Axis::Vertical => self.line + 1,
+        }
+    }
 }
 
 impl Grid {
-    fn find_horizontal_reflection(&self) -> Option<usize> {
-        for i in 0..self.cells.len() - 1 {
-            if self.cells[i] == self.cells[i + 1] {
-                let mut reflection = true;
-                for offset in 1..min(i + 1, self.cells.len() - i - 1) {
-                    if self.cells[i - offset] != self.cells[i + offset + 1] {
-                        reflection = false;
-                        break;
-                    }
-                }
-                if reflection {
-                    return Some(i);
+    /// Builds a grid, precomputing the bitmask of every row and column
+    fn new(cells: Box<[Box<[u8]>]>) -> Self {
+        let (rows, cols) = (cells.len(), cells[0].len());
+        let mut row_masks = vec![vec![0u128; cols.div_ceil(128)].into_boxed_slice(); rows];
+        let mut col_masks = vec![vec![0u128; rows.div_ceil(128)].into_boxed_slice(); cols];
+        for (i, row) in cells.iter().enumerate() {
+            for (j, &cell) in row.iter().enumerate() {
+                if cell == b'#' {
+                    row_masks[i][j / 128] |= 1 << (j % 128);
+                    col_masks[j][i / 128] |= 1 << (i % 128);
                 }
             }
         }
-        None
+        Grid {
+            cells,
+            row_masks: row_masks.into_boxed_slice(),
+            col_masks: col_masks.into_boxed_slice(),
+        }
     }
 
-    fn find_vertical_reflection(&self) -> Option<usize> {
-        for j in 0..self.cells[0].len() - 1 {
-            let mut possible_reflection = true;
-            for i in 0..self.cells.len() {
-                if self.cells[i][j] != self.cells[i][j + 1] {
-                    possible_reflection = false;
+    /// Every mirror line along the axis with at most `max_smudges` mismatched cells, comparing
+    /// whole rows or columns at once through their bitmasks
+    fn find_reflections(&self, axis: Axis, max_smudges: usize) -> Vec<Reflection> {
+        let masks = match axis {
+            Axis::Horizontal => &self.row_masks,
+            Axis::Vertical => &self.col_masks,
+        };
+        let lines = masks.len();
+
+        let mut reflections = Vec::new();
+        for line in 0..lines - 1 {
+            let pairs = (0..min(line + 1, lines - line - 1)).map(|offset| {
+                let (near, far) = (line - offset, line + offset + 1);
+                let difference = masks[near].iter().zip(&masks[far]).map(|(a, b)| a ^ b);
+                (near, difference)
+            });
+            let mut mismatches = 0;
+            for (_, difference) in pairs.clone() {
+                mismatches += difference
+                    .map(|word| word.count_ones() as usize)
+                    .sum::<usize>();
+                if mismatches > max_smudges {
                     break;
                 }
             }
-            if possible_reflection {
-                let mut reflection = true;
-                for offset in 1..min(j + 1, self.cells[0].len() - j - 1) {
-                    for i in 0..self.cells.len() {
-                        if self.cells[i][j - offset] != self.cells[i][j + offset + 1] {
-                            reflection = false;
-                            break;
-                        }
-                    }
-                    if !reflection {
-                        break;
+            if mismatches > max_smudges {
+                continue;
+            }
+
+            // Only unpack the differing bits into coordinates for the lines that qualify
+            let mut smudges = Vec::with_capacity(mismatches);
+            for (near, difference) in pairs {
+                for (word_index, mut word) in difference.enumerate() {
+                    while word != 0 {
+                        let position = word_index * 128 + word.trailing_zeros() as usize;
+                        smudges.push(match axis {
+                            Axis::Horizontal => (near, position),
+                            Axis::Vertical => (position, near),
+                        });
+                        word &= word - 1;
                     }
                 }
-                if reflection {
-                    return Some(j);
-                }
             }
+            smudges.sort_unstable();
+            reflections.push(Reflection {
+                axis,
+                line,
+                smudges,
+            });
         }
-        None
+        reflections
     }
 
-    fn find_smudged_horizontal_reflection(&self) -> Option<usize> {
-        for i in 0..self.cells.len() - 1 {
-            let mut smudges = 0;
-            for offset in 0..min(i + 1, self.cells.len() - i - 1) {
-                for j in 0..self.cells[0].len() {
-                    if self.cells[i - offset][j] != self.cells[i + offset + 1][j] {
-                        smudges += 1;
-                        if smudges > 1 {
-                            break;
-                        }
-                    }
+    /// The first reflection with exactly the given number of smudges, preferring vertical lines
+    fn find_reflection(&self, smudges: usize) -> Option<Reflection> {
+        [Axis::Vertical, Axis::Horizontal]
+            .into_iter()
+            .flat_map(|axis| self.find_reflections(axis, smudges))
+            .find(|r| r.smudges.len() == smudges)
+    }
+
+    /// Checks whether the region is mapped onto itself by `map`, returning the smudges if there
+    /// are at most `max_smudges`. Within each orbit of cells under `map`, every cell that differs
+    /// from the most common value in the orbit is a smudge.
+    fn check_symmetry(
+        &self,
+        top_left: (usize, usize),
+        size: (usize, usize),
+        map: impl Fn((usize, usize)) -> (usize, usize),
+        max_smudges: usize,
+    ) -> Option<Vec<(usize, usize)>> {
+        let mut smudges = Vec::new();
+        for i in top_left.0..top_left.0 + size.0 {
+            for j in top_left.1..top_left.1 + size.1 {
+                let mut orbit = vec![(i, j)];
+                let mut next = map((i, j));
+                while next != (i, j) {
+                    orbit.push(next);
+                    next = map(next);
                 }
-                if smudges > 1 {
-                    break;
+                // Visit each orbit once, from its smallest cell
+                if orbit.iter().any(|&cell| cell < (i, j)) {
+                    continue;
+                }
+                let damaged = orbit
+                    .iter()
+                    .filter(|&&(i, j)| self.cells[i][j] == b'#')
+                    .count();
+                let majority = if damaged * 2 > orbit.len() {
+                    b'#'
+                } else {
+                    b'.'
+                };
+                smudges.extend(
+                    orbit
+                        .into_iter()
+                        .filter(|&(i, j)| self.cells[i][j] != majority),
+                );
+                if smudges.len() > max_smudges {
+                    return None;
                 }
-            }
-            if smudges == 1 {
-                return Some(i);
             }
         }
-        None
+        Some(smudges)
     }
 
-    fn find_smudged_vertical_reflection(&self) -> Option<usize> {
-        for j in 0..self.cells[0].len() - 1 {
-            let mut smudges = 0;
-            for offset in 0..min(j + 1, self.cells[0].len() - j - 1) {
-                for i in 0..self.cells.len() {
-                    if self.cells[i][j - offset] != self.cells[i][j + offset + 1] {
-                        smudges += 1;
-                        if smudges > 1 {
-                            break;
-                        }
+    /// The 180° rotational symmetry of the whole grid, if it has at most `max_smudges`
+    fn find_half_turn_symmetry(&self, max_smudges: usize) -> Option<Symmetry> {
+        let size = (self.cells.len(), self.cells[0].len());
+        let smudges = self.check_symmetry(
+            (0, 0),
+            size,
+            |(i, j)| (size.0 - 1 - i, size.1 - 1 - j),
+            max_smudges,
+        )?;
+        Some(Symmetry {
+            kind: SymmetryKind::HalfTurn,
+            top_left: (0, 0),
+            size,
+            smudges,
+        })
+    }
+
+    /// Every square region of at least `min_size` with 90° rotational symmetry and at most
+    /// `max_smudges`
+    fn find_quarter_turn_symmetries(&self, min_size: usize, max_smudges: usize) -> Vec<Symmetry> {
+        let (rows, cols) = (self.cells.len(), self.cells[0].len());
+        let mut symmetries = Vec::new();
+        for size in min_size.max(1)..=min(rows, cols) {
+            for top in 0..=rows - size {
+                for left in 0..=cols - size {
+                    let map =
+                        |(i, j): (usize, usize)| (top + j - left, left + size - 1 - (i - top));
+                    if let Some(smudges) =
+                        self.check_symmetry((top, left), (size, size), map, max_smudges)
+                    {
+                        symmetries.push(Symmetry {
+                            kind: SymmetryKind::QuarterTurn,
+                            top_left: (top, left),
+                            size: (size, size),
+                            smudges,
+                        });
                     }
                 }
-                if smudges > 1 {
-                    break;
-                }
-            }
-            if smudges == 1 {
-                return Some(j);
             }
         }
-        None
+        symmetries
+    }
+
+    /// Every diagonal mirror line whose reflected region is at least `min_size` square, with at
+    /// most `max_smudges`. Like the row and column mirror lines, cells reflected off the edge of
+    /// the grid are ignored, which leaves a square region with the line as one of its diagonals.
+    fn find_diagonal_reflections(&self, min_size: usize, max_smudges: usize) -> Vec<Symmetry> {
+        let (rows, cols) = (self.cells.len(), self.cells[0].len());
+        let mut regions = Vec::new();
+        // Lines parallel to the main diagonal, through each cell of the top row and left column
+        for top in (1..rows).rev() {
+            regions.push((SymmetryKind::MainDiagonal, (top, 0), min(rows - top, cols)));
+        }
+        for left in 0..cols {
+            regions.push((
+                SymmetryKind::MainDiagonal,
+                (0, left),
+                min(rows, cols - left),
+            ));
+        }
+        // Lines parallel to the anti-diagonal, for each value of row + column
+        for sum in 0..rows + cols - 1 {
+            let top = sum.saturating_sub(cols - 1);
+            let left = sum.saturating_sub(rows - 1);
+            regions.push((
+                SymmetryKind::AntiDiagonal,
+                (top, left),
+                min(rows - 1, sum) - top + 1,
+            ));
+        }
+
+        regions
+            .into_iter()
+            .filter(|&(_, _, size)| size >= min_size.max(1))
+            .filter_map(|(kind, (top, left), size)| {
+                let map = |(i, j): (usize, usize)| match kind {
+                    SymmetryKind::MainDiagonal => (top + j - left, left + i - top),
+                    _ => (top + size - 1 - (j - left), left + size - 1 - (i - top)),
+                };
+                let smudges = self.check_symmetry((top, left), (size, size), map, max_smudges)?;
+                Some(Symmetry {
+                    kind,
+                    top_left: (top, left),
+                    size: (size, size),
+                    smudges,
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SymmetryKind {
+    /// 180° rotation about the centre of the region
+    HalfTurn,
+    /// 90° rotation about the centre of the square region
+    QuarterTurn,
+    /// Reflection in the top-left to bottom-right diagonal of the square region
+    MainDiagonal,
+    /// Reflection in the top-right to bottom-left diagonal of the square region
+    AntiDiagonal,
+}
+
+#[derive(Debug)]
+struct Symmetry {
+    kind: SymmetryKind,
+    /// The (row, column) of the top-left cell of the region
+    top_left: (usize, usize),
+    /// The (rows, columns) of the region
+    size: (usize, usize),
+    /// The (row, column) of each cell that differs from the rest of its orbit
+    smudges: Vec<(usize, usize)>,
+}
+
+impl fmt::Display for Symmetry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (top, left) = self.top_left;
+        let (bottom, right) = (top + self.size.0 - 1, left + self.size.1 - 1);
+        // The centre of a region may fall between cells
+        let centre = |a: usize, b: usize| {
+            if (a + b).is_multiple_of(2) {
+                format!("{}", (a + b) / 2)
+            } else {
+                format!("{}.5", (a + b) / 2)
+            }
+        };
+        match self.kind {
+            SymmetryKind::HalfTurn | SymmetryKind::QuarterTurn => write!(
+                f,
+                "{} about ({}, {})",
+                if self.kind == SymmetryKind::HalfTurn {
+                    "180° rotation"
+                } else {
+                    "90° rotation"
+                },
+                centre(top, bottom),
+                centre(left, right)
+            ),
+            SymmetryKind::MainDiagonal => write!(
+                f,
+                "reflection in the diagonal ({top}, {left})-({bottom}, {right})"
+            ),
+            SymmetryKind::AntiDiagonal => write!(
+                f,
+                "reflection in the diagonal ({top}, {right})-({bottom}, {left})"
+            ),
+        }?;
+        write!(f, " with {} smudge(s)", self.smudges.len())
     }
 }
 
@@ -131,17 +354,10 @@ impl Iterator for GridIterator {
     fn next(&mut self) -> Option<Self::Item> {
         let mut grid: Vec<Box<[u8]>> = vec![];
         loop {
-            let line = self.lines.next();
-            if let Some(l) = line
-                && !l.is_empty()
-            {
-                grid.push(l.into_bytes().into_boxed_slice());
-            } else if !grid.is_empty() {
-                return Some(Grid {
-                    cells: grid.into_boxed_slice(),
-                });
-            } else {
-                return None;
+            match self.lines.next() {
+                Some(l) if !l.is_empty() => grid.push(l.into_bytes().into_boxed_slice()),
+                _ if !grid.is_empty() => return Some(Grid::new(grid.into_boxed_slice())),
+                _ => return None,
             }
         }
     }
@@ -155,30 +371,58 @@ fn grids() -> impl Iterator<Item = Grid> {
 
 fn part_1() {
     let sum: usize = grids()
-        .map(|grid| {
-            if let Some(v) = grid.find_vertical_reflection() {
-                v + 1
-            } else if let Some(h) = grid.find_horizontal_reflection() {
-                (h + 1) * 100
-            } else {
-                0
-            }
-        })
+        .map(|grid| grid.find_reflection(0).map_or(0, |r| r.summary()))
         .sum();
     println!("Sum of reflection summaries: {sum}");
 }
 
 fn part_2() {
     let sum: usize = grids()
-        .map(|grid| {
-            if let Some(v) = grid.find_smudged_vertical_reflection() {
-                v + 1
-            } else if let Some(h) = grid.find_smudged_horizontal_reflection() {
-                (h + 1) * 100
-            } else {
-                0
-            }
-        })
+        .map(|grid| grid.find_reflection(1).map_or(0, |r| r.summary()))
         .sum();
     println!("Sum of smudged reflection summaries: {sum}");
 }
+
+fn part_smudges() {
+    let mut counts = [0; 3];
+    for grid in grids() {
+        for reflection in [Axis::Vertical, Axis::Horizontal]
+            .into_iter()
+            .flat_map(|axis| grid.find_reflections(axis, 2))
+        {
+            counts[reflection.smudges.len()] += 1;
+        }
+    }
+    println!(
+        "Mirror lines with 0, 1 and 2 smudges: {}, {}, {}",
+        counts[0], counts[1], counts[2]
+    );
+}
+
+fn part_symmetries() {
+    let mut half_turns = 0;
+    let mut diagonals = 0;
+    let mut largest_quarter_turn: Option<(usize, Symmetry)> = None;
+    for (n, grid) in grids().enumerate() {
+        half_turns += usize::from(grid.find_half_turn_symmetry(1).is_some());
+        diagonals += grid.find_diagonal_reflections(5, 1).len();
+        for symmetry in grid.find_quarter_turn_symmetries(3, 1) {
+            if largest_quarter_turn
+                .as_ref()
+                .is_none_or(|(_, largest)| symmetry.size > largest.size)
+            {
+                largest_quarter_turn = Some((n, symmetry));
+            }
+        }
+    }
+    println!("Grids with 180° symmetry (up to 1 smudge): {half_turns}");
+    println!("Diagonal reflections of at least 5x5 (up to 1 smudge): {diagonals}");
+    if let Some((n, symmetry)) = largest_quarter_turn {
+        println!(
+            "Largest 90° symmetric square (up to 1 smudge): {}x{} in grid {}, {symmetry}",
+            symmetry.size.0,
+            symmetry.size.1,
+            n + 1
+        );
+    }
+}
@@ -1,14 +1,21 @@
-use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
-use std::fs::File;
-use std::hash::Hash;
-use std::hash::Hasher;
-use std::io::{self, BufRead};
+use std::fs::{self, File};
+use std::io::{self, BufRead, Write};
 use std::path::Path;
+use std::str::FromStr;
 
 fn main() {
     part_1();
     part_2();
+
+    // Optionally run a custom tilt program, e.g. `cargo run -- "N E N W x1000"`, or write the
+    // spin cycle out as PPM frames, e.g. `cargo run -- --frames frames`
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        Some("--frames") => export_frames(&args.next().expect("has frames directory")),
+        Some(program) => run_program(program),
+        None => (),
+    }
 }
 
 fn read_lines<P>(filename: P) -> Box<dyn Iterator<Item = String>>
@@ -23,7 +30,7 @@ where
     )
 }
 
-#[derive(Hash)]
+#[derive(Clone, Copy)]
 enum Cell {
     Empty,
     CubeRock,
@@ -41,213 +48,400 @@ impl From<u8> for Cell {
     }
 }
 
-#[derive(Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Direction {
+    const ALL: [Self; 4] = [Self::North, Self::East, Self::South, Self::West];
+}
+
+/// A sequence of tilts, run as one round and repeated `repeats` times, written like
+/// `N W S E x1000000000`
+#[derive(Debug)]
+struct TiltProgram {
+    tilts: Vec<Direction>,
+    repeats: usize,
+}
+
+impl FromStr for TiltProgram {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tilts = Vec::new();
+        let mut repeats = 1;
+        for token in s.split_whitespace() {
+            if let Some(count) = token.strip_prefix('x') {
+                repeats = count
+                    .parse()
+                    .map_err(|_| format!("invalid repeat count '{count}'"))?;
+                continue;
+            }
+            tilts.push(match token {
+                "N" => Direction::North,
+                "E" => Direction::East,
+                "S" => Direction::South,
+                "W" => Direction::West,
+                _ => return Err(format!("invalid tilt '{token}'")),
+            });
+        }
+        if tilts.is_empty() {
+            return Err("program has no tilts".to_string());
+        }
+        Ok(TiltProgram { tilts, repeats })
+    }
+}
+
+/// A run of cells between cube rocks along the direction of a tilt, starting from the end the
+/// round rocks roll towards
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    start: usize,
+    step: isize,
+    len: usize,
+}
+
+impl Segment {
+    fn cells(self) -> impl Iterator<Item = usize> {
+        (0..self.len).map(move |k| self.start.wrapping_add_signed(self.step * k as isize))
+    }
+}
+
+#[derive(Clone)]
 struct Platform {
-    cells: Box<[Box<[Cell]>]>,
+    /// The cells in row-major order
+    cells: Box<[Cell]>,
+    width: usize,
+    /// The segments each tilt moves rocks within, indexed by direction
+    segments: [Box<[Segment]>; 4],
 }
 
 impl Platform {
     fn read() -> Self {
+        let mut width = 0;
+        let cells: Box<[Cell]> = read_lines("input.txt")
+            .flat_map(|line| {
+                width = line.len();
+                line.bytes().map(Cell::from).collect::<Vec<_>>()
+            })
+            .collect();
+        let segments = Direction::ALL.map(|direction| find_segments(&cells, width, direction));
         Self {
-            cells: read_lines("input.txt")
-                .map(|line| {
-                    line.bytes()
-                        .map(Cell::from)
-                        .collect::<Vec<_>>()
-                        .into_boxed_slice()
-                })
-                .collect::<Vec<_>>()
-                .into_boxed_slice(),
+            cells,
+            width,
+            segments,
         }
     }
 
-    fn tilt_cycle(&mut self) {
-        self.tilt_north();
-        self.tilt_west();
-        self.tilt_south();
-        self.tilt_east();
+    /// Rolls every round rock as far as it goes, by counting the round rocks in each segment and
+    /// packing them against its leading end
+    fn tilt(&mut self, direction: Direction) {
+        for &segment in &self.segments[direction as usize] {
+            let round_count = segment
+                .cells()
+                .filter(|&index| matches!(self.cells[index], Cell::RoundRock))
+                .count();
+            for (k, index) in segment.cells().enumerate() {
+                self.cells[index] = if k < round_count {
+                    Cell::RoundRock
+                } else {
+                    Cell::Empty
+                };
+            }
+        }
     }
 
-    fn tilt_north(&mut self) {
-        fn fill_round(cells: &mut [Box<[Cell]>], i: usize, j: usize, count: usize) {
-            for offset in 0..count {
-                cells[i + offset][j] = Cell::RoundRock;
+    /// The positions of the round rocks as a bitset in row-major order, which identifies the state
+    /// of the platform exactly since the cube rocks never move
+    fn round_rocks(&self) -> Box<[u64]> {
+        let mut bits = vec![0u64; self.cells.len().div_ceil(64)];
+        for (index, cell) in self.cells.iter().enumerate() {
+            if matches!(cell, Cell::RoundRock) {
+                bits[index / 64] |= 1 << (index % 64);
             }
         }
-        let mut round_count: Vec<usize> = vec![0; self.cells[0].len()];
-
-        for i in (0..self.cells.len()).rev() {
-            for (j, count) in round_count.iter_mut().enumerate() {
-                if i != 0 {
-                    match (&self.cells[i][j], count) {
-                        (Cell::RoundRock, count) => {
-                            self.cells[i][j] = Cell::Empty;
-                            *count += 1;
-                        }
-                        (Cell::CubeRock, count) if *count > 0 => {
-                            fill_round(&mut self.cells, i + 1, j, *count);
-                            *count = 0;
-                        }
-                        _ => (),
-                    }
-                } else if *count > 0 {
-                    match self.cells[i][j] {
-                        Cell::CubeRock | Cell::RoundRock => {
-                            fill_round(&mut self.cells, i + 1, j, *count);
-                        }
-                        Cell::Empty => {
-                            fill_round(&mut self.cells, i, j, *count);
-                        }
-                    }
-                }
+        bits.into_boxed_slice()
+    }
+
+    /// The platform with its round rocks moved to the positions in a `round_rocks` bitset
+    fn with_round_rocks(&self, bits: &[u64]) -> Self {
+        let mut platform = self.clone();
+        for (index, cell) in platform.cells.iter_mut().enumerate() {
+            if bits[index / 64] & (1 << (index % 64)) != 0 {
+                *cell = Cell::RoundRock;
+            } else if matches!(cell, Cell::RoundRock) {
+                *cell = Cell::Empty;
             }
         }
+        platform
     }
 
-    fn tilt_south(&mut self) {
-        fn fill_round(cells: &mut [Box<[Cell]>], i: usize, j: usize, count: usize) {
-            for offset in 0..count {
-                cells[i - offset][j] = Cell::RoundRock;
+    /// The total load on the support beams along the given edge, where each round rock weighs
+    /// its distance from the opposite edge
+    fn calculate_load(&self, edge: Direction) -> usize {
+        let (height, width) = (self.cells.len() / self.width, self.width);
+        let mut load = 0;
+        for (index, cell) in self.cells.iter().enumerate() {
+            if matches!(cell, Cell::RoundRock) {
+                let (i, j) = (index / width, index % width);
+                load += match edge {
+                    Direction::North => height - i,
+                    Direction::East => j + 1,
+                    Direction::South => i + 1,
+                    Direction::West => width - j,
+                };
             }
         }
-        let mut round_count: Vec<usize> = vec![0; self.cells[0].len()];
-
-        for i in 0..self.cells.len() {
-            for (j, count) in round_count.iter_mut().enumerate() {
-                if i != self.cells.len() - 1 {
-                    match (&self.cells[i][j], count) {
-                        (Cell::RoundRock, count) => {
-                            self.cells[i][j] = Cell::Empty;
-                            *count += 1;
-                        }
-                        (Cell::CubeRock, count) if *count > 0 => {
-                            fill_round(&mut self.cells, i - 1, j, *count);
-                            *count = 0;
-                        }
-                        _ => (),
-                    }
-                } else if *count > 0 {
-                    match self.cells[i][j] {
-                        Cell::CubeRock | Cell::RoundRock => {
-                            fill_round(&mut self.cells, i - 1, j, *count);
-                        }
-                        Cell::Empty => {
-                            fill_round(&mut self.cells, i, j, *count);
-                        }
-                    }
-                }
-            }
+        load
+    }
+}
+
+/// The side of each platform cell in pixels, and the height of the load chart below the platform
+const CELL_SIZE: usize = 2;
+const CHART_HEIGHT: usize = 40;
+
+/// An RGB image that can be written as a binary PPM file
+struct Frame {
+    width: usize,
+    height: usize,
+    pixels: Vec<[u8; 3]>,
+}
+
+impl Frame {
+    fn new(width: usize, height: usize) -> Self {
+        Frame {
+            width,
+            height,
+            pixels: vec![[0, 0, 0]; width * height],
         }
     }
 
-    fn tilt_west(&mut self) {
-        fn fill_round(cells: &mut [Box<[Cell]>], i: usize, j: usize, count: usize) {
-            for offset in 0..count {
-                cells[i][j + offset] = Cell::RoundRock;
+    fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, colour: [u8; 3]) {
+        for row in y..(y + height).min(self.height) {
+            for col in x..(x + width).min(self.width) {
+                self.pixels[row * self.width + col] = colour;
             }
         }
+    }
+
+    fn draw_border(&mut self, thickness: usize, colour: [u8; 3]) {
+        let (width, height) = (self.width, self.height);
+        self.fill_rect(0, 0, width, thickness, colour);
+        self.fill_rect(0, height - thickness, width, thickness, colour);
+        self.fill_rect(0, 0, thickness, height, colour);
+        self.fill_rect(width - thickness, 0, thickness, height, colour);
+    }
 
-        for i in 0..self.cells.len() {
-            let mut round_count = 0;
-            for j in (0..self.cells[i].len()).rev() {
-                if j != 0 {
-                    match (&self.cells[i][j], &mut round_count) {
-                        (Cell::RoundRock, count) => {
-                            self.cells[i][j] = Cell::Empty;
-                            *count += 1;
-                        }
-                        (Cell::CubeRock, count) if *count > 0 => {
-                            fill_round(&mut self.cells, i, j + 1, *count);
-                            *count = 0;
-                        }
-                        _ => (),
-                    }
-                } else if round_count > 0 {
-                    match self.cells[i][j] {
-                        Cell::CubeRock | Cell::RoundRock => {
-                            fill_round(&mut self.cells, i, j + 1, round_count);
-                        }
-                        Cell::Empty => {
-                            fill_round(&mut self.cells, i, j, round_count);
-                        }
-                    }
+    fn write_ppm(&self, path: &Path) -> io::Result<()> {
+        let mut file = io::BufWriter::new(File::create(path)?);
+        write!(file, "P6\n{} {}\n255\n", self.width, self.height)?;
+        for pixel in &self.pixels {
+            file.write_all(pixel)?;
+        }
+        file.flush()
+    }
+}
+
+impl Platform {
+    /// Draws the platform with space left below it for the load chart
+    fn render(&self) -> Frame {
+        let height = self.cells.len() / self.width;
+        let mut frame = Frame::new(self.width * CELL_SIZE, height * CELL_SIZE + CHART_HEIGHT);
+        for (index, cell) in self.cells.iter().enumerate() {
+            let colour = match cell {
+                Cell::Empty => [20, 20, 30],
+                Cell::CubeRock => [120, 120, 120],
+                Cell::RoundRock => [230, 200, 60],
+            };
+            let (i, j) = (index / self.width, index % self.width);
+            frame.fill_rect(j * CELL_SIZE, i * CELL_SIZE, CELL_SIZE, CELL_SIZE, colour);
+        }
+        frame
+    }
+}
+
+/// Draws the north load after each tilt so far as bars along the bottom of the frame, scaled to
+/// the range of all `loads`, with the tilts from `cycle_start` on in a different colour
+fn draw_load_chart(frame: &mut Frame, loads: &[usize], current: usize, cycle_start: usize) {
+    let min = loads.iter().copied().min().unwrap_or(0);
+    let range = (loads.iter().copied().max().unwrap_or(0) - min).max(1);
+    let top = frame.height - CHART_HEIGHT;
+    frame.fill_rect(0, top, frame.width, CHART_HEIGHT, [0, 0, 0]);
+    for (k, &load) in loads.iter().enumerate().take(current + 1) {
+        let x = k * (frame.width - 1) / (loads.len() - 1).max(1);
+        let bar = 1 + (load - min) * (CHART_HEIGHT - 1) / range;
+        let colour = if k < cycle_start {
+            [80, 160, 230]
+        } else {
+            [230, 90, 60]
+        };
+        frame.fill_rect(x, frame.height - bar, 1, bar, colour);
+    }
+}
+
+/// The segments between cube rocks along every line in the direction of a tilt
+fn find_segments(cells: &[Cell], width: usize, direction: Direction) -> Box<[Segment]> {
+    let height = cells.len() / width;
+    // The number of lines, their length, the first cell of the first line at the edge the rocks
+    // roll towards, the step along each line and the step between lines
+    let (lines, line_len, first, step, line_step) = match direction {
+        Direction::North => (width, height, 0, width as isize, 1),
+        Direction::South => (width, height, (height - 1) * width, -(width as isize), 1),
+        Direction::West => (height, width, 0, 1, width),
+        Direction::East => (height, width, width - 1, -1, width),
+    };
+
+    let mut segments = Vec::new();
+    for line in 0..lines {
+        let start = first + line * line_step;
+        let mut segment = Segment {
+            start,
+            step,
+            len: 0,
+        };
+        for index in (Segment {
+            len: line_len,
+            ..segment
+        })
+        .cells()
+        {
+            if matches!(cells[index], Cell::CubeRock) {
+                if segment.len > 0 {
+                    segments.push(segment);
                 }
+                segment = Segment {
+                    start: index.wrapping_add_signed(step),
+                    step,
+                    len: 0,
+                };
+            } else {
+                segment.len += 1;
             }
         }
+        if segment.len > 0 {
+            segments.push(segment);
+        }
     }
+    segments.into_boxed_slice()
+}
 
-    fn tilt_east(&mut self) {
-        fn fill_round(cells: &mut [Box<[Cell]>], i: usize, j: usize, count: usize) {
-            for offset in 0..count {
-                cells[i][j - offset] = Cell::RoundRock;
+/// The states a platform goes through under repeated rounds of tilts, up to its first repeated
+/// state
+struct CycleHistory {
+    platform: Platform,
+    /// The round rocks after each number of rounds, starting from zero
+    states: Vec<Box<[u64]>>,
+    /// The number of rounds after which the states start repeating
+    start: usize,
+    /// The number of rounds between repeated states
+    length: usize,
+}
+
+impl CycleHistory {
+    fn record(mut platform: Platform, tilts: &[Direction]) -> Self {
+        let mut seen: HashMap<Box<[u64]>, usize> = HashMap::new();
+        let mut states = Vec::new();
+        loop {
+            let rounds = states.len();
+            let state = platform.round_rocks();
+            if let Some(&start) = seen.get(&state) {
+                return CycleHistory {
+                    platform,
+                    states,
+                    start,
+                    length: rounds - start,
+                };
+            }
+            seen.insert(state.clone(), rounds);
+            states.push(state);
+            for &direction in tilts {
+                platform.tilt(direction);
             }
         }
+    }
 
-        for i in 0..self.cells.len() {
-            let mut round_count = 0;
-            for j in 0..self.cells[i].len() {
-                if j != self.cells[i].len() - 1 {
-                    match (&self.cells[i][j], &mut round_count) {
-                        (Cell::RoundRock, count) => {
-                            self.cells[i][j] = Cell::Empty;
-                            *count += 1;
-                        }
-                        (Cell::CubeRock, count) if *count > 0 => {
-                            fill_round(&mut self.cells, i, j - 1, *count);
-                            *count = 0;
-                        }
-                        _ => (),
-                    }
-                } else if round_count > 0 {
-                    match self.cells[i][j] {
-                        Cell::CubeRock | Cell::RoundRock => {
-                            fill_round(&mut self.cells, i, j - 1, round_count);
-                        }
-                        Cell::Empty => {
-                            fill_round(&mut self.cells, i, j, round_count);
-                        }
-                    }
-                }
-            }
+    /// The platform after any number of rounds, skipping over repeats of the cycle
+    fn platform_after(&self, rounds: usize) -> Platform {
+        let index = if rounds < self.states.len() {
+            rounds
+        } else {
+            self.start + (rounds - self.start) % self.length
+        };
+        self.platform.with_round_rocks(&self.states[index])
+    }
+
+    fn load_after(&self, rounds: usize, edge: Direction) -> usize {
+        self.platform_after(rounds).calculate_load(edge)
+    }
+}
+
+fn run_program(program: &str) {
+    let program: TiltProgram = program.parse().expect("valid tilt program");
+    let history = CycleHistory::record(Platform::read(), &program.tilts);
+    println!(
+        "Program repeats every {} rounds after {} rounds",
+        history.length, history.start
+    );
+    for edge in Direction::ALL {
+        println!(
+            "Load on the {edge:?} edge after {} rounds: {}",
+            program.repeats,
+            history.load_after(program.repeats, edge)
+        );
+    }
+}
+
+/// Writes a frame of the platform after every tilt of the spin cycle, up to the one at which the
+/// cycle is detected, which is outlined in red
+fn export_frames(dir: &str) {
+    let program: TiltProgram = "N W S E".parse().expect("valid tilt program");
+    let mut platform = Platform::read();
+    let history = CycleHistory::record(platform.clone(), &program.tilts);
+
+    let mut platforms = vec![platform.clone()];
+    for _ in 0..history.start + history.length {
+        for &direction in &program.tilts {
+            platform.tilt(direction);
+            platforms.push(platform.clone());
         }
     }
+    let loads: Vec<usize> = platforms
+        .iter()
+        .map(|platform| platform.calculate_load(Direction::North))
+        .collect();
+    let cycle_start = history.start * program.tilts.len();
+    let detected = platforms.len() - 1;
 
-    fn calculate_north_load(&self) -> usize {
-        self.cells
-            .iter()
-            .rev()
-            .enumerate()
-            .map(|(i, row)| row.iter().filter(|c| matches!(c, Cell::RoundRock)).count() * (i + 1))
-            .sum()
+    fs::create_dir_all(dir).expect("can create frames directory");
+    for (k, platform) in platforms.iter().enumerate() {
+        let mut frame = platform.render();
+        draw_load_chart(&mut frame, &loads, k, cycle_start);
+        if k == detected {
+            frame.draw_border(CELL_SIZE, [220, 40, 40]);
+        }
+        let path = Path::new(dir).join(format!("frame_{k:04}.ppm"));
+        frame.write_ppm(&path).expect("can write frame");
     }
+    println!(
+        "Wrote {} frames to {dir}, with the cycle detected at frame {detected}",
+        platforms.len()
+    );
 }
 
 fn part_1() {
     let mut platform = Platform::read();
-    platform.tilt_north();
-    let load: usize = platform.calculate_north_load();
+    platform.tilt(Direction::North);
+    let load: usize = platform.calculate_load(Direction::North);
     println!("Total load on north support beams after north tilt: {load}");
 }
 
 fn part_2() {
-    let mut platform = Platform::read();
-    let required_cycles = 1_000_000_000;
-    let mut states: HashMap<u64, usize> = HashMap::new();
-    for i in 0..required_cycles {
-        platform.tilt_cycle();
-        let mut hasher = DefaultHasher::new();
-        platform.hash(&mut hasher);
-        let hash = hasher.finish();
-        if let Some(&v) = states.get(&hash) {
-            let remaining_cycles = ((required_cycles - v - 2) % (i - v)) + 1;
-            for _ in 0..remaining_cycles {
-                platform.tilt_cycle();
-            }
-            break;
-        }
-        states.insert(hash, i);
-    }
-
-    let load: usize = platform.calculate_north_load();
+    let program: TiltProgram = "N W S E x1000000000".parse().expect("valid tilt program");
+    let history = CycleHistory::record(Platform::read(), &program.tilts);
+    let load = history.load_after(program.repeats, Direction::North);
     println!("Total load on north support beams after 1B cycles: {load}");
 }